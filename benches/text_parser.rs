@@ -1,15 +1,60 @@
+//! Parser benchmarks for spotting regressions between changes.
+//!
+//! `cargo bench` results are varying a lot from run to run, so they are not a proof of the speedup:
+//! compare several alternating runs of both revisions on an idle machine before drawing any conclusions.
 #![feature(test)]
 
 extern crate test;
 
+use std::fmt::Write;
 use std::str::FromStr;
 use test::Bencher;
 
+use unbound_telemetry::statistics::Parser;
 use unbound_telemetry::Statistics;
 
 static STATS: &str = include_str!("../assets/test_text_stats.txt");
 
+/// Statistics of a 32-thread instance which was queried for a lot of the unknown record types.
+fn large_stats() -> String {
+    let mut stats = String::with_capacity(STATS.len() * 16);
+    for thread in 0..32 {
+        for line in STATS.lines().filter(|line| line.starts_with("thread0.")) {
+            writeln!(stats, "thread{}.{}", thread, &line["thread0.".len()..]).unwrap();
+        }
+    }
+    for line in STATS.lines().filter(|line| !line.starts_with("thread")) {
+        stats.push_str(line);
+        stats.push('\n');
+    }
+    for rtype in 256..1256 {
+        writeln!(stats, "num.query.type.TYPE{}={}", rtype, rtype * 7).unwrap();
+    }
+
+    stats
+}
+
 #[bench]
 fn bench_parser(b: &mut Bencher) {
     b.iter(|| Statistics::from_str(STATS));
 }
+
+#[bench]
+fn bench_parser_large(b: &mut Bencher) {
+    let stats = large_stats();
+    b.bytes = stats.len() as u64;
+    b.iter(|| Statistics::from_str(&stats));
+}
+
+#[bench]
+fn bench_feed_line_large(b: &mut Bencher) {
+    let stats = large_stats();
+    b.bytes = stats.len() as u64;
+    b.iter(|| {
+        let mut parser = Parser::new();
+        for line in stats.lines() {
+            parser.feed_line(line).unwrap();
+        }
+        parser.finish()
+    });
+}
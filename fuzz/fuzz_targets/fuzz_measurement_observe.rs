@@ -1,8 +1,80 @@
 #![no_main]
+use std::collections::HashSet;
+
 use libfuzzer_sys::fuzz_target;
 
-use unbound_telemetry::{Statistics, Measurement};
+use unbound_telemetry::statistics::Parser;
+use unbound_telemetry::{Format, Labels, Measurement, MeasurementOptions, ParseMode};
+
+fuzz_target!(|data: String| {
+    // Unknown keys are exported as is with the passthrough enabled,
+    // so arbitrary data is reaching the metric names, help text and label values
+    let stats = match Parser::with_mode(ParseMode::Lenient).parse(&data) {
+        Ok(stats) => stats,
+        Err(..) => return,
+    };
+    let label = data.lines().next().unwrap_or_default();
 
-fuzz_target!(|data: Statistics| {
-    let _ = Measurement::observe(data).unwrap();
+    for format in [Format::Prometheus, Format::OpenMetrics, Format::Protobuf].iter() {
+        let options = MeasurementOptions {
+            passthrough: true,
+            format: *format,
+            labels: Labels::new().with("label", label),
+            ..Default::default()
+        };
+        let body = Measurement::observe_with(stats.clone(), &options).unwrap().drain();
+
+        if *format != Format::Protobuf {
+            let body = String::from_utf8(body).unwrap();
+            let mut series = HashSet::new();
+            for line in body.lines().filter(|line| !line.starts_with("# ")) {
+                // Scrape with the duplicated series is rejected by Prometheus completely
+                assert!(series.insert(check_sample(line)), "Duplicate series '{}'", line);
+            }
+        }
+    }
 });
+
+/// Panics if the text format sample line is malformed, returns the series (name and labels) otherwise.
+fn check_sample(line: &str) -> &str {
+    let name_end = line.find(|c| c == '{' || c == ' ').expect("Sample value is missing");
+    let (name, mut rest) = line.split_at(name_end);
+    assert!(name.starts_with("unbound_"), "Invalid metric name in '{}'", line);
+    assert!(
+        name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b':'),
+        "Invalid metric name in '{}'",
+        line
+    );
+
+    if let Some(labels) = rest.strip_prefix('{') {
+        let mut bytes = labels.bytes().enumerate();
+        let mut in_value = false;
+        let mut end = None;
+        while let Some((idx, byte)) = bytes.next() {
+            match (in_value, byte) {
+                (true, b'\\') => match bytes.next() {
+                    Some((_, b'\\')) | Some((_, b'"')) | Some((_, b'n')) => {}
+                    _ => panic!("Invalid escape sequence in '{}'", line),
+                },
+                (true, b'"') => in_value = false,
+                (false, b'"') => in_value = true,
+                (false, b'}') => {
+                    end = Some(idx);
+                    break;
+                }
+                _ => {}
+            }
+        }
+        let end = end.unwrap_or_else(|| panic!("Unterminated labels in '{}'", line));
+        rest = &labels[end + 1..];
+    }
+
+    let value = rest.strip_prefix(' ').expect("Sample value is missing");
+    assert!(
+        value.parse::<f64>().is_ok() || value == "+Inf" || value == "-Inf" || value == "NaN",
+        "Invalid sample value in '{}'",
+        line
+    );
+
+    &line[..line.len() - rest.len()]
+}
@@ -1,5 +1,8 @@
 use std::net::SocketAddr;
 use std::path::PathBuf;
+use std::time::Duration;
+
+use unbound_telemetry::{LabelLimit, Labels, Profile, ThreadSeries};
 
 #[derive(structopt::StructOpt, Debug, Clone)]
 pub struct Common {
@@ -23,6 +26,254 @@ pub struct Common {
         parse(try_from_str)
     )]
     pub log_level: log::Level,
+
+    /// Fail the scrape if unbound reports any statistics key not known to the exporter.
+    ///
+    /// By default unknown keys are ignored and reported via the `unbound_exporter_unknown_keys` metric.
+    #[structopt(long = "strict", global = true)]
+    pub strict: bool,
+
+    /// Skip malformed statistics lines instead of failing the scrape.
+    ///
+    /// Skipped lines are logged and counted by the `unbound_exporter_parse_errors` metric.
+    #[structopt(long = "lenient", global = true, conflicts_with = "strict")]
+    pub lenient: bool,
+
+    /// Export statistics keys not known to the exporter with the `unbound_raw_` prefix.
+    ///
+    /// Metric type is guessed: `num.*` keys are exported as counters and all other keys as gauges.
+    #[structopt(long = "passthrough", global = true)]
+    pub passthrough: bool,
+
+    /// Maximum size in bytes of the statistics response from unbound.
+    #[structopt(long = "max-response-size", default_value = "16777216", global = true)]
+    pub max_response_size: usize,
+
+    /// Export response time quantiles estimated from the histogram, ex. `--quantiles 0.5,0.9,0.99`.
+    ///
+    /// Quantiles are exported as the `unbound_response_time_quantile_seconds` gauges.
+    #[structopt(
+        long = "quantiles",
+        global = true,
+        use_delimiter = true,
+        parse(try_from_str = parse_quantile)
+    )]
+    pub quantiles: Vec<f64>,
+
+    /// Estimate quantiles for the queries answered since the previous scrape
+    /// instead of the whole unbound uptime.
+    #[structopt(long = "quantiles-interval", global = true, requires = "quantiles")]
+    pub quantiles_interval: bool,
+
+    /// Respond in the OpenMetrics format if the `Accept` request header prefers it, as Prometheus does by default.
+    ///
+    /// Note that OpenMetrics counter samples are always named with the `_total` suffix
+    /// (ex. `unbound_time_now_seconds_total`), so some names are different from the Prometheus text format.
+    #[structopt(long = "openmetrics", global = true)]
+    pub openmetrics: bool,
+
+    /// Export the series of every pool member with the `member` label
+    /// along with the merged ones, if `--control-interface` is repeated.
+    #[structopt(long = "pool-members", global = true)]
+    pub pool_members: bool,
+
+    /// Seconds to merge the counters of the unavailable pool member from its last observed statistics.
+    ///
+    /// After that the member is dropped from the merged statistics, so the merged counters are going down.
+    #[structopt(long = "pool-stale-timeout", default_value = "300", global = true)]
+    pub pool_stale_timeout: u64,
+
+    /// Export the derived ratio gauges, ex. `unbound_cache_hit_ratio` or `unbound_answers_servfail_ratio`.
+    ///
+    /// Ratios are exported for the whole unbound uptime with the `window="lifetime"` label
+    /// and for the time since the previous scrape with the `window="interval"` label.
+    #[structopt(long = "ratios", global = true)]
+    pub ratios: bool,
+
+    /// Export the response time histogram with custom bucket upper bounds in seconds,
+    /// ex. `--histogram-buckets 0.001,0.01,0.1,1`.
+    ///
+    /// By default all 40 unbound buckets are exported. Bounds which are not equal to
+    /// the unbound ones (powers of two in microseconds up to 0.524288 and in seconds after that)
+    /// are approximate: queries are counted at the first bound not lower than their unbound bucket bound.
+    #[structopt(
+        long = "histogram-buckets",
+        global = true,
+        use_delimiter = true,
+        parse(try_from_str = parse_bound)
+    )]
+    pub histogram_buckets: Vec<Duration>,
+
+    /// Add the constant label to every exported series, ex. `--label site=ams1 --label role=edge`.
+    ///
+    /// Might be repeated. Labels set by the exporter itself (ex. `thread`) are taking precedence.
+    #[structopt(
+        long = "label",
+        global = true,
+        number_of_values = 1,
+        parse(try_from_str = parse_label)
+    )]
+    pub labels: Vec<(String, String)>,
+
+    /// Prefix of the exported metric names, ex. `dns_resolver_`; might be empty.
+    #[structopt(
+        long = "namespace",
+        default_value = "unbound_",
+        global = true,
+        parse(try_from_str = parse_namespace)
+    )]
+    pub namespace: String,
+
+    /// Export only the metric families matching any of the glob patterns, ex. `--include 'query_*,answers_*'`.
+    ///
+    /// Families are matched by their names without namespace, ex. `query_types_total`;
+    /// `*` matches any number of characters and `?` matches exactly one.
+    #[structopt(long = "include", global = true, use_delimiter = true, number_of_values = 1)]
+    pub include: Vec<String>,
+
+    /// Do not export the metric families matching any of the glob patterns, ex. `--exclude 'query_types_total,dnscrypt_*'`.
+    ///
+    /// Exclusion takes precedence over the `--include` option.
+    #[structopt(long = "exclude", global = true, use_delimiter = true, number_of_values = 1)]
+    pub exclude: Vec<String>,
+
+    /// Export only the metric families of the naming profile, for the dashboards built for another exporter.
+    ///
+    /// `all` exports every family, `kumina` and `letsencrypt` export the families of the corresponding
+    /// `unbound_exporter` and `native` exports all families except the deprecated duplicates.
+    #[structopt(
+        long = "profile",
+        default_value = "all",
+        global = true,
+        possible_values = &["all", "kumina", "letsencrypt", "native"],
+        parse(try_from_str = parse_profile)
+    )]
+    pub profile: Profile,
+
+    /// Series to export for the per-thread metrics (ex. `unbound_queries_total`).
+    ///
+    /// `per-thread` exports series with the `thread="N"` label only, `with-total` adds the `thread="total"` series,
+    /// `total` exports the `thread="total"` series only and `unlabeled` exports the totals without the `thread` label.
+    #[structopt(
+        long = "thread-series",
+        default_value = "per-thread",
+        global = true,
+        possible_values = &["per-thread", "with-total", "total", "unlabeled"],
+        parse(try_from_str = parse_thread_series)
+    )]
+    pub thread_series: ThreadSeries,
+
+    /// Limit the `type` label values of the `unbound_query_types_total` metric,
+    /// either to the number of the most queried types (ex. `20`) or to the types listed (ex. `A,AAAA,PTR`).
+    ///
+    /// Other types are counted as `type="other"`. All types are exported by default.
+    #[structopt(long = "query-types-limit", global = true, parse(try_from_str = parse_label_limit))]
+    pub query_types_limit: Option<LabelLimit>,
+
+    /// Limit the `class` label values of the `unbound_query_classes_total` metric,
+    /// same as the `--query-types-limit` option does.
+    #[structopt(long = "query-classes-limit", global = true, parse(try_from_str = parse_label_limit))]
+    pub query_classes_limit: Option<LabelLimit>,
+
+    /// Limit the `opcode` label values of the `unbound_query_opcodes_total` metric,
+    /// same as the `--query-types-limit` option does.
+    #[structopt(long = "query-opcodes-limit", global = true, parse(try_from_str = parse_label_limit))]
+    pub query_opcodes_limit: Option<LabelLimit>,
+}
+
+fn parse_label_limit(s: &str) -> Result<LabelLimit, String> {
+    if let Ok(k) = s.parse::<usize>() {
+        return Ok(LabelLimit::Top(k));
+    }
+
+    let allowed = s
+        .split(',')
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    if allowed.is_empty() {
+        Err("either the number or the list of label values is expected".to_string())
+    } else {
+        Ok(LabelLimit::Allow(allowed))
+    }
+}
+
+fn parse_thread_series(s: &str) -> Result<ThreadSeries, String> {
+    match s {
+        "per-thread" => Ok(ThreadSeries::PerThread),
+        "with-total" => Ok(ThreadSeries::PerThreadAndTotal),
+        "total" => Ok(ThreadSeries::Total),
+        "unlabeled" => Ok(ThreadSeries::Unlabeled),
+        _ => Err(format!("unknown thread series '{}'", s)),
+    }
+}
+
+fn parse_profile(s: &str) -> Result<Profile, String> {
+    match s {
+        "all" => Ok(Profile::All),
+        "kumina" => Ok(Profile::Kumina),
+        "letsencrypt" => Ok(Profile::Letsencrypt),
+        "native" => Ok(Profile::Native),
+        _ => Err(format!("unknown profile '{}'", s)),
+    }
+}
+
+fn parse_namespace(s: &str) -> Result<String, String> {
+    let mut chars = s.chars();
+    let is_valid = match chars.next() {
+        None => true,
+        Some(c) if c.is_ascii_alphabetic() || c == '_' || c == ':' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':')
+        }
+        Some(_) => false,
+    };
+
+    if is_valid {
+        Ok(s.to_string())
+    } else {
+        Err(format!("namespace '{}' is not a valid metric name prefix", s))
+    }
+}
+
+fn parse_label(s: &str) -> Result<(String, String), String> {
+    let mut parts = s.splitn(2, '=');
+    match (parts.next(), parts.next()) {
+        (Some(name), Some(value)) if Labels::is_valid_name(name) => Ok((name.to_string(), value.to_string())),
+        (Some(name), Some(_)) => Err(format!("invalid label name '{}'", name)),
+        _ => Err(format!("label '{}' should be in the `name=value` format", s)),
+    }
+}
+
+fn parse_bound(s: &str) -> Result<Duration, String> {
+    match s.parse::<f64>() {
+        Ok(secs) if secs > 0.0 && secs < u64::MAX as f64 => Ok(Duration::from_secs_f64(secs)),
+        Ok(secs) => Err(format!("bucket bound {} is out of range", secs)),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Output format of the `describe` subcommand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DescribeFormat {
+    Markdown,
+    Json,
+}
+
+fn parse_describe_format(s: &str) -> Result<DescribeFormat, String> {
+    match s {
+        "markdown" => Ok(DescribeFormat::Markdown),
+        "json" => Ok(DescribeFormat::Json),
+        _ => Err(format!("unknown format '{}'", s)),
+    }
+}
+
+fn parse_quantile(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(q) if (0.0..=1.0).contains(&q) => Ok(q),
+        Ok(q) => Err(format!("quantile {} is out of the 0..1 range", q)),
+        Err(e) => Err(e.to_string()),
+    }
 }
 
 #[derive(structopt::StructOpt, Debug)]
@@ -45,8 +296,10 @@ pub enum Arguments {
     #[cfg(unix)]
     Uds {
         /// Local socket path.
-        #[structopt(name = "socket", long = "control-interface")]
-        socket: PathBuf,
+        ///
+        /// Might be repeated in order to export the merged statistics of multiple instances.
+        #[structopt(name = "socket", long = "control-interface", required = true, number_of_values = 1)]
+        socket: Vec<PathBuf>,
         #[structopt(flatten)]
         common: Common,
     },
@@ -72,10 +325,33 @@ pub enum Arguments {
         key: Option<PathBuf>,
 
         /// TLS socket hostname.
-        #[structopt(name = "interface", long = "control-interface", default_value = "127.0.0.1:8953")]
+        ///
+        /// Might be repeated in order to export the merged statistics of multiple instances.
+        #[structopt(
+            name = "interface",
+            long = "control-interface",
+            default_value = "127.0.0.1:8953",
+            number_of_values = 1
+        )]
         // Note that at this point we are not using `SocketAddr` type,
         // because we might need to do the DNS resolving later.
-        interface: String,
+        interface: Vec<String>,
+
+        #[structopt(flatten)]
+        common: Common,
+    },
+    /// Print the exported metric families and exit.
+    ///
+    /// Namespace, profile and include/exclude options are applied to the list.
+    Describe {
+        /// Output format, either `markdown` table or `json`.
+        #[structopt(
+            long = "format",
+            default_value = "markdown",
+            possible_values = &["markdown", "json"],
+            parse(try_from_str = parse_describe_format)
+        )]
+        format: DescribeFormat,
 
         #[structopt(flatten)]
         common: Common,
@@ -90,6 +366,7 @@ impl Arguments {
     pub fn common(&self) -> &Common {
         match self {
             Arguments::Tcp { common, .. } => common,
+            Arguments::Describe { common, .. } => common,
             #[cfg(unix)]
             Arguments::Shm { common, .. } => common,
             #[cfg(unix)]
@@ -0,0 +1,133 @@
+use std::io::{self, Write};
+
+use unbound_telemetry::{Descriptor, MeasurementOptions, Origin, ThreadSeries};
+
+use crate::cli::{Arguments, DescribeFormat};
+use crate::server::build_options;
+
+/// Print the families exported with the `config` given into the stdout.
+pub fn print(config: &Arguments, format: DescribeFormat) -> io::Result<()> {
+    let options = build_options(config);
+    let namespace = options.namespace.as_deref().unwrap_or("unbound_");
+    let families = families(&options);
+
+    let stdout = io::stdout();
+    let mut w = stdout.lock();
+    match format {
+        DescribeFormat::Markdown => {
+            writeln!(w, "| Metric | Type | Labels | Description |")?;
+            writeln!(w, "| ------ | ---- | ------ | ----------- |")?;
+            for (descriptor, labels) in families {
+                let labels = labels.iter().map(|label| format!("`{}`", label)).collect::<Vec<_>>();
+                writeln!(
+                    w,
+                    "| `{}{}` | {} | {} | {} |",
+                    namespace,
+                    descriptor.name,
+                    descriptor.kind.as_str(),
+                    labels.join(", "),
+                    descriptor.help.replace('|', "\\|")
+                )?;
+            }
+        }
+        DescribeFormat::Json => {
+            let entries = families
+                .into_iter()
+                .map(|(descriptor, labels)| {
+                    let labels = labels.iter().map(|label| json_string(label)).collect::<Vec<_>>();
+                    format!(
+                        "  {{\"name\": {}, \"type\": {}, \"labels\": [{}], \"help\": {}}}",
+                        json_string(&format!("{}{}", namespace, descriptor.name)),
+                        json_string(descriptor.kind.as_str()),
+                        labels.join(", "),
+                        json_string(descriptor.help)
+                    )
+                })
+                .collect::<Vec<_>>();
+            writeln!(w, "[\n{}\n]", entries.join(",\n"))?;
+        }
+    }
+
+    w.flush()
+}
+
+/// Families exported with the `options` given, along with their labels.
+fn families(options: &MeasurementOptions) -> Vec<(&'static Descriptor, Vec<&'static str>)> {
+    Descriptor::all()
+        .iter()
+        .filter(|descriptor| options.filter.allows(descriptor.name) && options.profile.exports(descriptor.name))
+        .filter(|descriptor| match descriptor.origin {
+            Origin::Quantiles => !options.quantiles.is_empty(),
+            Origin::Ratios => options.ratios,
+            Origin::Statistics | Origin::Exporter => true,
+        })
+        .map(|descriptor| {
+            let labels = descriptor
+                .labels
+                .iter()
+                .cloned()
+                .filter(|label| *label != "thread" || options.thread_series != ThreadSeries::Unlabeled)
+                .collect();
+            (descriptor, labels)
+        })
+        .collect()
+}
+
+/// Quote and escape the `value` as the JSON string.
+fn json_string(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use unbound_telemetry::{MeasurementOptions, ThreadSeries};
+
+    use super::{families, json_string};
+
+    fn find(options: &MeasurementOptions, name: &str) -> Option<Vec<&'static str>> {
+        families(options)
+            .into_iter()
+            .find(|(descriptor, _)| descriptor.name == name)
+            .map(|(_, labels)| labels)
+    }
+
+    #[test]
+    fn test_families() {
+        let options = MeasurementOptions::default();
+        assert_eq!(find(&options, "queries_total"), Some(vec!["thread"]));
+        assert_eq!(find(&options, "response_time_quantile_seconds"), None);
+        assert_eq!(find(&options, "cache_hit_ratio"), None);
+
+        let options = MeasurementOptions {
+            quantiles: vec![0.5],
+            ratios: true,
+            thread_series: ThreadSeries::Unlabeled,
+            ..Default::default()
+        };
+        assert_eq!(find(&options, "queries_total"), Some(vec![]));
+        assert_eq!(find(&options, "response_time_quantile_seconds"), Some(vec!["quantile"]));
+        assert_eq!(find(&options, "cache_hit_ratio"), Some(vec!["window"]));
+    }
+
+    #[test]
+    fn test_json_string() {
+        assert_eq!(json_string(""), r#""""#);
+        assert_eq!(json_string("Cache hits"), r#""Cache hits""#);
+        assert_eq!(json_string(r#"say "hi" \ bye"#), r#""say \"hi\" \\ bye""#);
+        assert_eq!(json_string("line\nbreak\ttab\u{1}"), r#""line\nbreak\u0009tab\u0001""#);
+        assert_eq!(json_string("unicode: ü"), r#""unicode: ü""#);
+    }
+}
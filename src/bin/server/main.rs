@@ -3,12 +3,17 @@ use std::error::Error;
 use structopt::StructOpt;
 
 mod cli;
+mod describe;
 mod server;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
     let config = self::cli::Arguments::from_args();
     simple_logger::init_with_level(config.common().log_level)?;
+    if let self::cli::Arguments::Describe { format, .. } = &config {
+        self::describe::print(&config, *format)?;
+        return Ok(());
+    }
     self::server::serve(config).await?;
 
     Ok(())
@@ -1,11 +1,15 @@
+use std::collections::HashMap;
 use std::error::Error;
 use std::io;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::time;
 
 use hyper::service::{make_service_fn, service_fn};
 use hyper::{header::HeaderValue, Body, Method, Request, Response, Server, StatusCode};
-use unbound_telemetry::{Measurement, RemoteControlSource, Source, TextTransport, TlsTransport};
+use unbound_telemetry::{
+    Filter, Format, LabelSelection, Labels, Measurement, MeasurementOptions, ParseMode, PoolSource,
+    RemoteControlSource, Source, Statistics, TextTransport, TlsTransport, Window,
+};
 #[cfg(unix)]
 use unbound_telemetry::{SharedMemorySource, UdsTransport};
 
@@ -15,7 +19,115 @@ static INDEX_BODY: &str = include_str!("../../../assets/index.html");
 
 struct Context {
     config: cli::Arguments,
-    source: Box<dyn Source + Send + Sync + 'static>,
+    // Single instance is a pool with one member, which statistics are passed as is
+    source: PoolSource,
+    options: MeasurementOptions,
+    // Last observed statistics of each series set, stored only if measurement needs them
+    previous: Mutex<HashMap<Series, Statistics>>,
+    // Last successfully observed statistics of the pool members and the time they were observed at,
+    // counters of which are merged instead of the fresh ones if member is not available
+    members: Mutex<HashMap<String, (time::Instant, Statistics)>>,
+    // Label values selected by the `LabelLimit::Top` limits for each series set
+    selections: Mutex<HashMap<Series, LabelSelection>>,
+}
+
+/// Set of the series exported from the same statistics between scrapes,
+/// which is keeping its own state for the interval metrics and label limits.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Series {
+    /// Merged statistics of the whole pool (or the single instance).
+    Pool,
+    /// Pool member requested via the `instance` query parameter.
+    Instance(String),
+    /// Pool member exported along with the pool via the `--pool-members` flag.
+    Member(String),
+}
+
+impl Series {
+    fn requested(instance: Option<&str>) -> Series {
+        match instance {
+            None => Series::Pool,
+            Some(name) => Series::Instance(name.to_string()),
+        }
+    }
+}
+
+/// Statistics observed for one scrape.
+struct Observation {
+    statistics: Statistics,
+    // Fresh statistics of the pool members, `None` for the failed ones;
+    // empty if a single instance was observed
+    members: Vec<(String, Option<Statistics>)>,
+}
+
+impl Context {
+    /// Store the `statistics` observed for the `series` and return the previously stored ones.
+    fn remember(&self, series: Series, statistics: &Statistics) -> Option<Statistics> {
+        if !self.options.needs_previous() {
+            return None;
+        }
+
+        let mut previous = self.previous.lock().unwrap_or_else(|e| e.into_inner());
+        previous.insert(series, statistics.clone())
+    }
+
+    /// Observe the requested `instance` or, if it is not set, all the pool members.
+    ///
+    /// Failed pool members are logged and the counters of their last observed statistics are merged instead,
+    /// so the merged counters are not going backwards, until the `--pool-stale-timeout` passes;
+    /// observation fails only if all members have failed.
+    async fn observe(&self, source: &(dyn Source + Send + Sync), instance: Option<&str>) -> io::Result<Observation> {
+        if instance.is_some() || self.source.len() < 2 {
+            return source.observe().await.map(|statistics| Observation {
+                statistics,
+                members: Vec::new(),
+            });
+        }
+
+        let observations = self.source.observe_members().await;
+        let now = time::Instant::now();
+        let timeout = time::Duration::from_secs(self.config.common().pool_stale_timeout);
+        let mut error = None;
+        let mut statistics = Statistics::default();
+        let mut members = Vec::with_capacity(observations.len());
+        let mut last = self.members.lock().unwrap_or_else(|e| e.into_inner());
+        for (name, observation) in observations {
+            match observation {
+                Ok(fresh) => {
+                    statistics.merge(&fresh);
+                    let _ = last.insert(name.to_string(), (now, fresh.clone()));
+                    members.push((name.to_string(), Some(fresh)));
+                }
+                Err(e) => {
+                    match last.get(name) {
+                        Some((observed, stale)) if now.duration_since(*observed) < timeout => {
+                            log::warn!(
+                                "Pool member is not available, merging its last observed counters: {}",
+                                e
+                            );
+                            statistics.merge_counters(stale);
+                        }
+                        Some(_) => {
+                            log::warn!(
+                                "Pool member is not available for more than {:?}, dropping its last observed counters: {}",
+                                timeout,
+                                e
+                            );
+                            let _ = last.remove(name);
+                        }
+                        None => log::warn!("Pool member is not available: {}", e),
+                    }
+                    error = Some(e);
+                    members.push((name.to_string(), None));
+                }
+            }
+        }
+
+        match error {
+            Some(e) if members.iter().all(|(_, statistics)| statistics.is_none()) => Err(e),
+            _ => Ok(Observation { statistics, members }),
+        }
+    }
 }
 
 async fn handler(req: Request<Body>, context: Arc<Context>) -> hyper::Result<Response<Body>> {
@@ -24,19 +136,58 @@ async fn handler(req: Request<Body>, context: Arc<Context>) -> hyper::Result<Res
         (&Method::GET, "/") => Ok(Response::new(Body::from(INDEX_BODY))),
         // Observing statistics
         (&Method::GET, path) if path == context.config.common().path => {
+            let instance = requested_instance(&req);
+            let source = match select_source(&context.source, instance.as_deref()) {
+                Ok(source) => source,
+                Err(instance) => return Ok(render_unknown_instance(&instance)),
+            };
+
+            // Families requested via `collect[]` are narrowing the configured ones
+            let filter = context
+                .options
+                .filter
+                .clone()
+                .include(query_values(&req, "collect[]"))
+                .exclude(query_values(&req, "exclude[]"));
+            let options = MeasurementOptions {
+                format: negotiate_format(&req, context.config.common().openmetrics),
+                filter,
+                ..context.options.clone()
+            };
+
             let start = time::Instant::now();
-            let observation = context.source.observe().await;
+            let observation = context.observe(source, instance.as_deref()).await;
             let elapsed = time::Instant::now().duration_since(start);
 
             let mut response = observation
-                .and_then(|statistics| {
-                    let mut m = Measurement::observe(statistics)?;
+                .and_then(|observation| {
+                    let mut selections = context.selections.lock().unwrap_or_else(|e| e.into_inner());
+                    let series = Series::requested(instance.as_deref());
+                    let selection = selections.entry(series.clone()).or_default();
+                    let previous = context.remember(series, &observation.statistics);
+                    let mut m =
+                        Measurement::observe_selected(observation.statistics, previous.as_ref(), selection, &options)?;
+                    if context.config.common().pool_members {
+                        for (name, statistics) in observation.members.iter() {
+                            if let Some(statistics) = statistics {
+                                let series = Series::Member(name.clone());
+                                let selection = selections.entry(series.clone()).or_default();
+                                let previous = context.remember(series, statistics);
+                                let labels = Labels::new().with("member", name.as_str());
+                                m.observe_labeled(statistics.clone(), previous.as_ref(), selection, &options, &labels)?;
+                            }
+                        }
+                    }
 
                     // These two metrics are not related directly to the unbound,
                     // but we want to provide some extra data
-                    m.counter("up", "This Unbound instance is up and running").set(1)?;
-                    m.gauge("scrape_duration_seconds", "Time spent on metrics scraping")
-                        .set(elapsed)?;
+                    let mut up = m.described("up")?;
+                    up.set(1)?;
+                    for (name, statistics) in observation.members.iter() {
+                        let member_up = if statistics.is_some() { 1 } else { 0 };
+                        up.set_with_label("member", name.as_str(), member_up)?;
+                    }
+                    m.described("scrape_duration_seconds")?.set(elapsed)?;
 
                     Ok(m.drain())
                 })
@@ -44,9 +195,40 @@ async fn handler(req: Request<Body>, context: Arc<Context>) -> hyper::Result<Res
                 .or_else::<hyper::Error, _>(|e| Ok(render_error(e)))
                 .unwrap_or_else(|_| unreachable!("Err variant is excluded by the combinators chain"));
 
+            let content_type = if response.status() == StatusCode::OK {
+                options.format.content_type()
+            } else {
+                "text/plain"
+            };
             response
                 .headers_mut()
-                .insert("Content-Type", HeaderValue::from_static("text/plain"));
+                .insert("Content-Type", HeaderValue::from_static(content_type));
+
+            Ok(response)
+        }
+
+        // Raw statistics for the non-Prometheus consumers
+        #[cfg(feature = "serde")]
+        (&Method::GET, "/stats.json") => {
+            let instance = requested_instance(&req);
+            let source = match select_source(&context.source, instance.as_deref()) {
+                Ok(source) => source,
+                Err(instance) => return Ok(render_unknown_instance(&instance)),
+            };
+
+            let mut response = match context.observe(source, instance.as_deref()).await {
+                Ok(observation) => match serde_json::to_vec(&observation.statistics) {
+                    Ok(body) => Response::new(Body::from(body)),
+                    Err(e) => render_error(e),
+                },
+                Err(e) => render_error(e),
+            };
+
+            if response.status() == StatusCode::OK {
+                response
+                    .headers_mut()
+                    .insert("Content-Type", HeaderValue::from_static("application/json"));
+            }
 
             Ok(response)
         }
@@ -74,8 +256,23 @@ async fn handler(req: Request<Body>, context: Arc<Context>) -> hyper::Result<Res
 pub async fn serve(config: cli::Arguments) -> Result<(), Box<dyn Error + Send + Sync>> {
     let server_config = (*config.common()).clone();
     let source = build_source(&config)?;
+    let options = build_options(&config);
+    if source.len() > 1 {
+        log::info!(
+            "Exporting merged statistics of {} instances: {}",
+            source.len(),
+            source.names().collect::<Vec<_>>().join(", ")
+        );
+    }
 
-    let context = Arc::new(Context { config, source });
+    let context = Arc::new(Context {
+        config,
+        source,
+        options,
+        previous: Mutex::new(HashMap::new()),
+        members: Mutex::new(HashMap::new()),
+        selections: Mutex::new(HashMap::new()),
+    });
     let service = make_service_fn(move |_| {
         let handler_context = context.clone();
 
@@ -108,8 +305,62 @@ fn render_error<T: Error + std::fmt::Debug>(e: T) -> Response<Body> {
     response
 }
 
-fn build_source(config: &cli::Arguments) -> io::Result<Box<dyn Source + Send + Sync + 'static>> {
-    let source = match config {
+fn render_unknown_instance(name: &str) -> Response<Body> {
+    let mut response = Response::new(Body::from(format!("# Unknown instance: {}", name)));
+    *response.status_mut() = StatusCode::NOT_FOUND;
+
+    response
+}
+
+pub fn build_options(config: &cli::Arguments) -> MeasurementOptions {
+    MeasurementOptions {
+        passthrough: config.common().passthrough,
+        quantiles: config.common().quantiles.clone(),
+        quantiles_window: if config.common().quantiles_interval {
+            Window::Interval
+        } else {
+            Window::Lifetime
+        },
+        ratios: config.common().ratios,
+        // Negotiated for each request separately
+        format: Format::Prometheus,
+        histogram_buckets: match config.common().histogram_buckets.clone() {
+            bounds if bounds.is_empty() => None,
+            mut bounds => {
+                bounds.sort_unstable();
+                bounds.dedup();
+                Some(bounds)
+            }
+        },
+        labels: config
+            .common()
+            .labels
+            .iter()
+            .fold(Labels::new(), |labels, (name, value)| {
+                labels.with(name.clone(), value.as_str())
+            }),
+        namespace: Some(config.common().namespace.clone()),
+        filter: Filter::new()
+            .include(config.common().include.iter().cloned())
+            .exclude(config.common().exclude.iter().cloned()),
+        profile: config.common().profile,
+        thread_series: config.common().thread_series,
+        query_types_limit: config.common().query_types_limit.clone().unwrap_or_default(),
+        query_classes_limit: config.common().query_classes_limit.clone().unwrap_or_default(),
+        query_opcodes_limit: config.common().query_opcodes_limit.clone().unwrap_or_default(),
+    }
+}
+
+fn build_source(config: &cli::Arguments) -> io::Result<PoolSource> {
+    let mode = match config.common() {
+        common if common.strict => ParseMode::Strict,
+        common if common.lenient => ParseMode::Lenient,
+        _ => ParseMode::Tolerant,
+    };
+    let max_size = config.common().max_response_size;
+
+    let mut pool = PoolSource::new();
+    match config {
         cli::Arguments::Tcp {
             ca: Some(ca),
             cert: Some(cert),
@@ -117,9 +368,11 @@ fn build_source(config: &cli::Arguments) -> io::Result<Box<dyn Source + Send + S
             interface,
             ..
         } => {
-            let transport = TlsTransport::new(ca, cert, key, interface.clone())?;
-            let source = RemoteControlSource::new(transport);
-            Box::new(source) as Box<_>
+            for interface in interface {
+                let transport = TlsTransport::new(ca, cert, key, interface.clone())?;
+                let source = RemoteControlSource::with_mode(transport, mode).max_size(max_size);
+                pool.push(interface.clone(), Box::new(source));
+            }
         }
         cli::Arguments::Tcp {
             ca: None,
@@ -128,21 +381,212 @@ fn build_source(config: &cli::Arguments) -> io::Result<Box<dyn Source + Send + S
             interface,
             ..
         } => {
-            let transport = TextTransport::new(interface.clone())?;
-            let source = RemoteControlSource::new(transport);
-
-            Box::new(source) as Box<_>
+            for interface in interface {
+                let transport = TextTransport::new(interface.clone())?;
+                let source = RemoteControlSource::with_mode(transport, mode).max_size(max_size);
+                pool.push(interface.clone(), Box::new(source));
+            }
         }
         cli::Arguments::Tcp { .. } => unreachable!("CLI validation should handle this case"),
+        cli::Arguments::Describe { .. } => unreachable!("Families are described without the statistics source"),
         #[cfg(unix)]
         cli::Arguments::Uds { socket, .. } => {
-            let transport = UdsTransport::new(socket);
-            let source = RemoteControlSource::new(transport);
-            Box::new(source) as Box<_>
+            for socket in socket {
+                let transport = UdsTransport::new(socket);
+                let source = RemoteControlSource::with_mode(transport, mode).max_size(max_size);
+                pool.push(socket.display().to_string(), Box::new(source));
+            }
         }
         #[cfg(unix)]
-        cli::Arguments::Shm { shm_key, .. } => Box::new(SharedMemorySource::new(*shm_key)) as Box<_>,
+        cli::Arguments::Shm { shm_key, .. } => {
+            pool.push(shm_key.to_string(), Box::new(SharedMemorySource::new(*shm_key)));
+        }
+    };
+
+    Ok(pool)
+}
+
+/// Pick the exposition format by the `Accept` header, which Prometheus sends as
+/// `application/openmetrics-text;version=1.0.0,application/openmetrics-text;version=0.0.1;q=0.75,text/plain;version=0.0.4;q=0.5,*/*;q=0.1`
+/// or, if the native histograms are enabled, with the
+/// `application/vnd.google.protobuf;proto=io.prometheus.client.MetricFamily;encoding=delimited` range first.
+///
+/// Explicitly requested format with the highest quality wins; on ties protobuf is preferred over OpenMetrics
+/// and OpenMetrics over the Prometheus text format, which is used by default.
+/// OpenMetrics is not negotiated unless `allow_openmetrics` is set, as some metric names are different in it.
+fn negotiate_format(req: &Request<Body>, allow_openmetrics: bool) -> Format {
+    let accept = match req
+        .headers()
+        .get(hyper::header::ACCEPT)
+        .and_then(|value| value.to_str().ok())
+    {
+        Some(accept) => accept,
+        None => return Format::Prometheus,
     };
 
-    Ok(source)
+    let mut protobuf = 0.0f64;
+    let mut openmetrics = 0.0f64;
+    let mut text = 0.0f64;
+    for range in accept.split(',') {
+        let mut params = range.split(';').map(str::trim);
+        let media_type = params.next().unwrap_or_default().to_ascii_lowercase();
+        let mut quality = 1.0;
+        let mut version = None;
+        let mut proto = None;
+        let mut encoding = None;
+        for param in params {
+            let mut parts = param.splitn(2, '=').map(str::trim);
+            match (parts.next(), parts.next()) {
+                (Some("q"), Some(value)) => quality = value.parse().unwrap_or(0.0),
+                (Some("version"), Some(value)) => version = Some(value),
+                (Some("proto"), Some(value)) => proto = Some(value),
+                (Some("encoding"), Some(value)) => encoding = Some(value),
+                _ => {}
+            }
+        }
+
+        match (media_type.as_str(), version) {
+            ("application/vnd.google.protobuf", _)
+                if proto == Some("io.prometheus.client.MetricFamily") && encoding == Some("delimited") =>
+            {
+                protobuf = protobuf.max(quality)
+            }
+            ("application/openmetrics-text", None) | ("application/openmetrics-text", Some("1.0.0"))
+                if allow_openmetrics =>
+            {
+                openmetrics = openmetrics.max(quality)
+            }
+            ("text/plain", _) | ("text/*", _) | ("*/*", _) => text = text.max(quality),
+            _ => {}
+        }
+    }
+
+    if protobuf > 0.0 && protobuf >= openmetrics && protobuf >= text {
+        Format::Protobuf
+    } else if openmetrics > 0.0 && openmetrics >= text {
+        Format::OpenMetrics
+    } else {
+        Format::Prometheus
+    }
+}
+
+/// Pool member name from the `instance` query parameter, ex. `/metrics?instance=10.0.0.1:8953`.
+fn requested_instance(req: &Request<Body>) -> Option<String> {
+    query_values(req, "instance").into_iter().next()
+}
+
+/// Decoded values of all the query parameters with the `key` name,
+/// ex. `collect[]` for the `/metrics?collect[]=query_*&collect%5B%5D=answers_*`.
+fn query_values(req: &Request<Body>, key: &str) -> Vec<String> {
+    let query = match req.uri().query() {
+        Some(query) => query,
+        None => return Vec::new(),
+    };
+
+    query
+        .split('&')
+        .filter_map(|pair| {
+            let mut parts = pair.splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some(name), Some(value)) if percent_decode(name) == key => Some(percent_decode(value)),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Pick the pool member by its name or the whole pool if name is not set.
+///
+/// Unknown instance name is returned as an error.
+fn select_source<'c>(
+    pool: &'c PoolSource,
+    instance: Option<&str>,
+) -> Result<&'c (dyn Source + Send + Sync + 'static), String> {
+    match instance {
+        None => Ok(pool),
+        Some(name) => pool.member(name).ok_or_else(|| name.to_string()),
+    }
+}
+
+/// Decode the `%XX` sequences and `+` signs of the query parameter value.
+///
+/// Malformed sequences are kept as is, they will not match any instance name anyway.
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut idx = 0;
+    while idx < bytes.len() {
+        match bytes[idx] {
+            b'%' if idx + 2 < bytes.len()
+                && bytes[idx + 1].is_ascii_hexdigit()
+                && bytes[idx + 2].is_ascii_hexdigit() =>
+            {
+                // Both are ASCII hex digits, so it is safe to slice and parse them
+                let byte = u8::from_str_radix(&value[idx + 1..idx + 3], 16).expect("Valid hex digits");
+                decoded.push(byte);
+                idx += 3;
+                continue;
+            }
+            b'+' => decoded.push(b' '),
+            byte => decoded.push(byte),
+        }
+        idx += 1;
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use hyper::{Body, Request};
+    use unbound_telemetry::{Format, Measurement, MeasurementOptions, Statistics};
+
+    use super::negotiate_format;
+
+    static STATS: &str = include_str!("../../../assets/test_text_stats.txt");
+
+    // Sent by Prometheus by default
+    static ACCEPT: &str =
+        "application/openmetrics-text;version=1.0.0,application/openmetrics-text;version=0.0.1;q=0.75,\
+                           text/plain;version=0.0.4;q=0.5,*/*;q=0.1";
+
+    fn request(accept: &str) -> Request<Body> {
+        Request::get("/metrics")
+            .header("Accept", accept)
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_negotiate_format() {
+        assert_eq!(negotiate_format(&request(ACCEPT), false), Format::Prometheus);
+        assert_eq!(negotiate_format(&request(ACCEPT), true), Format::OpenMetrics);
+        assert_eq!(negotiate_format(&request("text/plain"), true), Format::Prometheus);
+    }
+
+    #[test]
+    fn test_default_names() {
+        let options = MeasurementOptions {
+            format: negotiate_format(&request(ACCEPT), false),
+            ..Default::default()
+        };
+        let mut m = Measurement::observe_with(Statistics::from_str(STATS).unwrap(), &options).unwrap();
+        m.described("up").unwrap().set(1).unwrap();
+        let body = String::from_utf8(m.drain()).unwrap();
+
+        for name in [
+            "unbound_up 1",
+            "unbound_time_now_seconds ",
+            "unbound_time_elapsed_seconds ",
+            "unbound_answers_bogus ",
+            "unbound_answers_bogus_total ",
+        ]
+        .iter()
+        {
+            assert!(body.lines().any(|line| line.starts_with(name)), "{} is missing", name);
+        }
+        assert!(!body.contains("# EOF"));
+    }
 }
@@ -22,8 +22,11 @@ mod metrics;
 mod sources;
 pub mod statistics;
 
-pub use self::metrics::Measurement;
-pub use self::sources::{RemoteControlSource, Source, TextTransport, TlsTransport};
+pub use self::metrics::{
+    Descriptor, Filter, Format, LabelLimit, LabelSelection, Labels, Measurement, MeasurementOptions, MetricKind,
+    Origin, Profile, ThreadSeries, Window,
+};
+pub use self::sources::{PoolSource, RemoteControlSource, Source, TextTransport, TlsTransport};
 #[cfg(unix)]
 pub use self::sources::{SharedMemorySource, UdsTransport};
-pub use self::statistics::{ParseError, Statistics};
+pub use self::statistics::{ParseError, ParseErrorKind, ParseMode, Statistics, StatisticsDelta, StatisticsRates};
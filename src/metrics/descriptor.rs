@@ -0,0 +1,456 @@
+//! Declarations of all metric families exported by the exporter.
+//!
+//! Families are written by looking up their descriptors by name (see `Measurement::described`),
+//! so the type and help of each family are declared exactly once.
+//! Passthrough families (`raw_*`) are not described, as their names are not known in advance.
+
+use std::collections::HashMap;
+
+use once_cell::sync::Lazy;
+
+/// Reason for the family to be exported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Origin {
+    /// `unbound` statistics, exported by default.
+    Statistics,
+    /// Response time quantiles, exported only if `MeasurementOptions::quantiles` are set.
+    Quantiles,
+    /// Derived ratios, exported only if `MeasurementOptions::ratios` is enabled.
+    Ratios,
+    /// Metrics of the exporter itself.
+    Exporter,
+}
+
+/// Metric family type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetricKind {
+    Counter,
+    Gauge,
+    Histogram,
+}
+
+impl MetricKind {
+    /// Type name as used in the `# TYPE` line of the text formats.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            MetricKind::Counter => "counter",
+            MetricKind::Gauge => "gauge",
+            MetricKind::Histogram => "histogram",
+        }
+    }
+}
+
+/// Declaration of the metric family.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Descriptor {
+    /// Family name without namespace, ex. `query_types_total`.
+    pub name: &'static str,
+    pub kind: MetricKind,
+    pub help: &'static str,
+    /// Names of the labels set by the exporter, not including the constant ones,
+    /// the `member` label of the pool members series and the `le` label of the histogram buckets.
+    ///
+    /// Note that the `thread` label might be omitted, see `ThreadSeries::Unlabeled`,
+    /// and the `member` label of `up` is set for the pool members only.
+    pub labels: &'static [&'static str],
+    pub origin: Origin,
+}
+
+impl Descriptor {
+    /// Find the descriptor of the family `name` (without namespace).
+    pub fn find(name: &str) -> Option<&'static Descriptor> {
+        INDEX.get(name).copied()
+    }
+
+    /// All described families in the order they are exported.
+    pub fn all() -> &'static [Descriptor] {
+        DESCRIPTORS
+    }
+}
+
+// Families are looked up for each of the thread series, so the linear search is too slow
+static INDEX: Lazy<HashMap<&'static str, &'static Descriptor>> = Lazy::new(|| {
+    DESCRIPTORS
+        .iter()
+        .map(|descriptor| (descriptor.name, descriptor))
+        .collect()
+});
+
+const fn counter(name: &'static str, labels: &'static [&'static str], help: &'static str) -> Descriptor {
+    Descriptor {
+        name,
+        kind: MetricKind::Counter,
+        help,
+        labels,
+        origin: Origin::Statistics,
+    }
+}
+
+const fn gauge(name: &'static str, labels: &'static [&'static str], help: &'static str) -> Descriptor {
+    Descriptor {
+        name,
+        kind: MetricKind::Gauge,
+        help,
+        labels,
+        origin: Origin::Statistics,
+    }
+}
+
+const fn histogram(name: &'static str, help: &'static str) -> Descriptor {
+    Descriptor {
+        name,
+        kind: MetricKind::Histogram,
+        help,
+        labels: &[],
+        origin: Origin::Statistics,
+    }
+}
+
+impl Descriptor {
+    const fn origin(self, origin: Origin) -> Descriptor {
+        Descriptor { origin, ..self }
+    }
+}
+
+const THREAD: &[&str] = &["thread"];
+
+static DESCRIPTORS: &[Descriptor] = &[
+    // Common
+    gauge("num_threads", &[], "The number of threads to create to serve clients"),
+    // Time
+    counter("time_up_seconds_total", &[], "Uptime since server boot in seconds"),
+    counter("time_now_seconds", &[], "Current time in seconds since UNIX epoch"),
+    counter(
+        "time_elapsed_seconds",
+        &[],
+        "Time since last statistics printout in seconds",
+    ),
+    // Memory
+    gauge("memory_caches_bytes", &["cache"], "Memory in bytes in use by caches"),
+    gauge("memory_modules_bytes", &["module"], "Memory in bytes in use by modules"),
+    counter(
+        "module_queries_total",
+        &["module"],
+        "Total number of queries that were handled by the module",
+    ),
+    gauge(
+        "memory_http_bytes",
+        &["http"],
+        "Memory in bytes in use by HTTP/2 queries",
+    ),
+    gauge(
+        "memory_stream_wait_count",
+        &[],
+        "The number of bytes in the stream wait buffers",
+    ),
+    // Queries
+    counter(
+        "query_tcp_total",
+        &[],
+        "Total number of queries that were made using TCP towards the server",
+    ),
+    counter(
+        "query_tcp_out_total",
+        &[],
+        "Total number of queries that were made using TCP outwards the server",
+    ),
+    counter(
+        "query_tls_total",
+        &[],
+        "Total number of queries that were made using TLS towards the server",
+    ),
+    counter(
+        "query_tls_resume_total",
+        &[],
+        "Total number of queries that were made using TLS resumption",
+    ),
+    counter(
+        "query_ipv6_total",
+        &[],
+        "Total number of queries that were made using IPv6 toward the server",
+    ),
+    counter(
+        "query_https_total",
+        &[],
+        "Total number of queries that were made using HTTPS",
+    ),
+    counter(
+        "query_edns_DO_total",
+        &[],
+        "Total number of queries that had an EDNS OPT record with the DO (DNSSEC OK) bit set present",
+    ),
+    counter(
+        "query_edns_present_total",
+        &[],
+        "Total number of queries that had an EDNS OPT record present",
+    ),
+    counter(
+        "query_ratelimited_total",
+        &[],
+        "Total number of queries that had been rate limited",
+    ),
+    // Validation
+    counter(
+        "answers_secure_total",
+        &[],
+        "Total amount of answers that were secure (AD)",
+    ),
+    // Deprecated version to maintain compatibility
+    counter(
+        "answers_bogus",
+        &[],
+        "Total amount of answers that were bogus (withheld as SERVFAIL)",
+    ),
+    counter(
+        "answers_bogus_total",
+        &[],
+        "Total amount of answers that were bogus (withheld as SERVFAIL)",
+    ),
+    counter(
+        "rrset_bogus_total",
+        &[],
+        "Total number of rrsets marked bogus by the validator",
+    ),
+    // Cache count (deprecated, exposed only to maintain compatibility with `kumina/unbound_exporter`)
+    gauge("msg_cache_count", &[], "The number of messages cached"),
+    gauge("rrset_cache_count", &[], "The number of rrset cached"),
+    gauge("cache_count_total", &["type"], "The number of cached entries"),
+    counter(
+        "unwanted_queries_total",
+        &[],
+        "Total number of queries that were refused or dropped because they failed the access control settings.",
+    ),
+    counter(
+        "unwanted_replies_total",
+        &[],
+        "Total number of replies that were unwanted or unsolicited",
+    ),
+    // Answers and queries by the DNS parameters
+    counter(
+        "answer_rcodes_total",
+        &["rcode"],
+        "Total number of answers to queries, from cache or from recursion, by response code.",
+    ),
+    counter(
+        "answer_ede_total",
+        &["code"],
+        "Total number of answers with a given Extended DNS Error code",
+    ),
+    counter(
+        "rpz_action_total",
+        &["action"],
+        "Total number of triggered Response Policy Zone actions",
+    ),
+    counter(
+        "query_opcodes_total",
+        &["opcode"],
+        "Total number of queries with a given query opcode",
+    ),
+    counter(
+        "query_types_total",
+        &["type"],
+        "Total number of queries with a given query type",
+    ),
+    counter(
+        "query_classes_total",
+        &["class"],
+        "Total number of queries with a given query class",
+    ),
+    gauge(
+        "exporter_labels_folded",
+        &["label"],
+        "The number of label values folded into the `other` value by the exporter limits",
+    )
+    .origin(Origin::Exporter),
+    counter(
+        "query_flags_total",
+        &["flag"],
+        "Total number of queries that had a given flag set in the header",
+    ),
+    // Exporter
+    gauge(
+        "exporter_unknown_keys",
+        &[],
+        "The number of statistics keys which are not known to the exporter",
+    )
+    .origin(Origin::Exporter),
+    gauge(
+        "exporter_parse_errors",
+        &[],
+        "The number of malformed statistics lines skipped by the exporter",
+    )
+    .origin(Origin::Exporter),
+    // Response time
+    gauge(
+        "response_time_quantile_seconds",
+        &["quantile"],
+        "Query response time quantiles in seconds, estimated from the response time histogram",
+    )
+    .origin(Origin::Quantiles),
+    histogram("response_time_seconds", "Query response time in seconds"),
+    // Ratios
+    gauge(
+        "cache_hit_ratio",
+        &["window"],
+        "Share of the queries answered from the cache among all cache lookups",
+    )
+    .origin(Origin::Ratios),
+    gauge(
+        "prefetch_ratio",
+        &["window"],
+        "Share of the cache prefetches among all queries received",
+    )
+    .origin(Origin::Ratios),
+    gauge(
+        "answers_servfail_ratio",
+        &["window"],
+        "Share of the SERVFAIL answers among all answers",
+    )
+    .origin(Origin::Ratios),
+    gauge(
+        "answers_bogus_ratio",
+        &["window"],
+        "Share of the bogus answers among the DNSSEC validated (secure or bogus) ones",
+    )
+    .origin(Origin::Ratios),
+    gauge(
+        "request_list_avg_to_max_ratio",
+        &["window"],
+        "Average size of the request list relative to the largest size it has reached since the start",
+    )
+    .origin(Origin::Ratios),
+    // Per-thread
+    counter("queries_total", THREAD, "Total number of queries received"),
+    counter(
+        "queries_ip_ratelimited_total",
+        THREAD,
+        "Total number of queries rate limited by IP",
+    ),
+    counter(
+        "cache_hits_total",
+        THREAD,
+        "Total number of queries that were successfully answered using a cache lookup.",
+    ),
+    counter(
+        "cache_misses_total",
+        THREAD,
+        "Total number of cache queries that needed recursive processing.",
+    ),
+    counter("prefetches_total", THREAD, "Total number of cache prefetches performed"),
+    // Deprecated since unbound version 1.10.1
+    counter(
+        "zero_ttl_responses_total",
+        THREAD,
+        "Total number of replies with ttl zero, because they served an expired cache entry.",
+    ),
+    // Added since unbound version 1.10.1
+    counter(
+        "expired_responses_total",
+        THREAD,
+        "Total number of replies that served an expired cache entry.",
+    ),
+    counter(
+        "dnscrypt_valid_queries_total",
+        THREAD,
+        "Total number of queries that were encrypted and successfully decapsulated by dnscrypt",
+    ),
+    counter(
+        "dnscrypt_cert_queries_total",
+        THREAD,
+        "Total number of queries that were requesting dnscrypt certificates",
+    ),
+    counter(
+        "dnscrypt_cleartext_queries_total",
+        THREAD,
+        "Total number of queries received on dnscrypt port that were cleartext and not a request for certificates",
+    ),
+    counter(
+        "dnscrypt_malformed_queries_total",
+        THREAD,
+        "Total number of requests that were neither cleartext, not valid dnscrypt messages",
+    ),
+    gauge(
+        "request_list_current_all",
+        THREAD,
+        "Current size of the request list, including internally generated queries",
+    ),
+    gauge(
+        "request_list_current_user",
+        THREAD,
+        "Current size of the request list, only counting the requests from client queries",
+    ),
+    counter(
+        "request_list_exceeded_total",
+        THREAD,
+        "Number of queries that were dropped because the request list was full",
+    ),
+    counter(
+        "request_list_overwritten_total",
+        THREAD,
+        "Total number of requests in the request list that were overwritten by newer entries",
+    ),
+    gauge(
+        "recursion_time_seconds_avg",
+        THREAD,
+        "Average time it took to answer queries that needed recursive processing (does not include in-cache requests)",
+    ),
+    gauge(
+        "recursion_time_seconds_median",
+        THREAD,
+        "The median of the time it took to answer queries that needed recursive processing",
+    ),
+    gauge(
+        "tcp_usage_current",
+        THREAD,
+        "Number of the currently held TCP buffers for incoming connections",
+    ),
+    // Written by the server along with the statistics
+    gauge("up", &["member"], "This Unbound instance is up and running").origin(Origin::Exporter),
+    gauge("scrape_duration_seconds", &[], "Time spent on metrics scraping").origin(Origin::Exporter),
+];
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::{Descriptor, MetricKind};
+    use crate::{Labels, Measurement};
+
+    #[test]
+    fn test_descriptors() {
+        for (idx, descriptor) in Descriptor::all().iter().enumerate() {
+            assert!(
+                Descriptor::all()[..idx]
+                    .iter()
+                    .all(|other| other.name != descriptor.name),
+                "Family {} is described twice",
+                descriptor.name
+            );
+            assert!(descriptor.name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_'));
+            assert!(descriptor.labels.iter().all(|label| Labels::is_valid_name(label)));
+            assert!(!descriptor.help.is_empty());
+        }
+
+        let descriptor = Descriptor::find("query_types_total").unwrap();
+        assert_eq!(descriptor.kind, MetricKind::Counter);
+        assert_eq!(descriptor.labels, &["type"]);
+        assert_eq!(Descriptor::find("raw_num_query_foo"), None);
+    }
+
+    #[test]
+    fn test_described() {
+        let mut m = Measurement::with_buffer_capacity(1024);
+        assert!(m.described("queries_total").is_ok());
+        assert!(m.described_histogram("response_time_seconds").is_ok());
+
+        for result in [
+            m.described("querys_total").err(),
+            m.described("response_time_seconds").err(),
+            m.described_histogram("queries_total").err(),
+        ]
+        .iter()
+        {
+            assert_eq!(result.as_ref().map(io::Error::kind), Some(io::ErrorKind::InvalidInput));
+        }
+    }
+}
@@ -0,0 +1,128 @@
+/// Allow and deny lists of the metric families.
+///
+/// Families are matched by their names without namespace (ex. `query_types_total`)
+/// against glob patterns, where `*` matches any number of characters and `?` matches exactly one.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Filter {
+    // Family should match any pattern of each group
+    include: Vec<Vec<String>>,
+    exclude: Vec<String>,
+}
+
+impl Filter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allow only the families matching any of the `patterns`.
+    ///
+    /// Repeated calls are narrowing the allowed families further, empty `patterns` are ignored.
+    pub fn include<I, S>(mut self, patterns: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let group = patterns.into_iter().map(Into::into).collect::<Vec<_>>();
+        if !group.is_empty() {
+            self.include.push(group);
+        }
+
+        self
+    }
+
+    /// Deny the families matching any of the `patterns`.
+    pub fn exclude<I, S>(mut self, patterns: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.exclude.extend(patterns.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn allows(&self, family: &str) -> bool {
+        let included = self
+            .include
+            .iter()
+            .all(|group| group.iter().any(|pattern| glob_match(pattern, family)));
+
+        included && !self.exclude.iter().any(|pattern| glob_match(pattern, family))
+    }
+}
+
+/// Match the `name` against the glob `pattern` with the `*` and `?` wildcards.
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let (pattern, name) = (pattern.as_bytes(), name.as_bytes());
+    let (mut p, mut n) = (0, 0);
+    // Position of the last `*` in the pattern and of the name byte it was tried against
+    let mut backtrack = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(b'?') => {
+                p += 1;
+                n += 1;
+            }
+            Some(byte) if *byte == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            // Let the last `*` consume one more byte and try again
+            _ => match backtrack {
+                Some((star, consumed)) => {
+                    backtrack = Some((star, consumed + 1));
+                    p = star + 1;
+                    n = consumed + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|byte| *byte == b'*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{glob_match, Filter};
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("query_types_total", "query_types_total"));
+        assert!(glob_match("*", "query_types_total"));
+        assert!(glob_match("query_*", "query_types_total"));
+        assert!(glob_match("*_total", "query_types_total"));
+        assert!(glob_match("*dnscrypt*", "dnscrypt_valid_queries_total"));
+        assert!(glob_match("query_?ypes_total", "query_types_total"));
+        assert!(glob_match("q*t*s*l", "query_types_total"));
+        assert!(!glob_match("query_types", "query_types_total"));
+        assert!(!glob_match("*_seconds", "query_types_total"));
+        assert!(!glob_match("?", ""));
+        assert!(glob_match("", ""));
+    }
+
+    #[test]
+    fn test_filter() {
+        let filter = Filter::new();
+        assert!(filter.allows("query_types_total"));
+
+        let filter = Filter::new().exclude(vec!["query_types_total", "*dnscrypt*"]);
+        assert!(!filter.allows("query_types_total"));
+        assert!(!filter.allows("dnscrypt_valid_queries_total"));
+        assert!(filter.allows("queries_total"));
+
+        let filter = Filter::new()
+            .include(vec!["query_*", "queries_total"])
+            .include(vec!["*_total"])
+            .exclude(vec!["*dnscrypt*"]);
+        assert!(filter.allows("query_types_total"));
+        assert!(filter.allows("queries_total"));
+        assert!(!filter.allows("dnscrypt_valid_queries_total"));
+        assert!(!filter.allows("query_buffer"));
+        assert!(!filter.allows("answers_rcodes_total"));
+    }
+}
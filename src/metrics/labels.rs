@@ -0,0 +1,145 @@
+use std::borrow::Cow;
+
+use super::value::MetricValue;
+
+/// Ordered set of the `name="value"` label pairs.
+///
+/// Values are rendered once when the label is added,
+/// so the same set can be cheaply used for multiple samples.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Labels {
+    pairs: Vec<(Cow<'static, str>, Vec<u8>)>,
+}
+
+/// Empty set for the guards created without the `Measurement`.
+pub(crate) static NO_LABELS: Labels = Labels { pairs: Vec::new() };
+
+impl Labels {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the label, see `Labels::push`.
+    pub fn with<N, V>(mut self, name: N, value: V) -> Self
+    where
+        N: Into<Cow<'static, str>>,
+        V: MetricValue,
+    {
+        let _ = self.push(name, value);
+        self
+    }
+
+    /// Add the label or replace the value of the already added label with the same name.
+    pub fn push<N, V>(&mut self, name: N, value: V) -> &mut Self
+    where
+        N: Into<Cow<'static, str>>,
+        V: MetricValue,
+    {
+        let name = name.into();
+        let mut rendered = Vec::with_capacity(16);
+        value.write(&mut rendered).expect("Writing into the Vec can't fail");
+
+        match self.pairs.iter_mut().find(|(known, _)| *known == name) {
+            Some(pair) => pair.1 = rendered,
+            None => self.pairs.push((name, rendered)),
+        }
+
+        self
+    }
+
+    /// Check if the label name matches the `[a-zA-Z_][a-zA-Z0-9_]*` regex
+    /// and is not prefixed with `__`, which is reserved for the internal use.
+    pub fn is_valid_name(name: &str) -> bool {
+        let mut bytes = name.bytes();
+        let starts_valid = matches!(bytes.next(), Some(b) if b.is_ascii_alphabetic() || b == b'_');
+
+        starts_valid && bytes.all(|b| b.is_ascii_alphanumeric() || b == b'_') && !name.starts_with("__")
+    }
+
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &[u8])> {
+        self.pairs.iter().map(|(name, value)| (name.as_ref(), value.as_slice()))
+    }
+
+    /// Labels from this set followed by the `other` ones, which are taking precedence on the same names.
+    pub fn union(&self, other: &Labels) -> Labels {
+        let mut union = self.clone();
+        for (name, value) in other.pairs.iter() {
+            match union.pairs.iter_mut().find(|(known, _)| known == name) {
+                Some(pair) => pair.1 = value.clone(),
+                None => union.pairs.push((name.clone(), value.clone())),
+            }
+        }
+
+        union
+    }
+
+    /// Constant labels from this set followed by the sample `labels`.
+    ///
+    /// Sample labels are taking precedence over the constant ones with the same name.
+    pub(crate) fn merge<'a>(&'a self, labels: &[(&'a str, &'a [u8])]) -> Vec<(&'a str, &'a [u8])> {
+        let mut merged = Vec::with_capacity(self.pairs.len() + labels.len());
+        merged.extend(
+            self.iter()
+                .filter(|(name, _)| labels.iter().all(|(other, _)| other != name)),
+        );
+        merged.extend_from_slice(labels);
+
+        merged
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Labels;
+
+    #[test]
+    fn test_labels() {
+        let labels = Labels::new()
+            .with("site", "ams1")
+            .with("thread", 1u64)
+            .with("site", "fra1");
+        assert_eq!(labels.len(), 2);
+        assert_eq!(
+            labels.iter().collect::<Vec<_>>(),
+            vec![("site", &b"fra1"[..]), ("thread", &b"1"[..])]
+        );
+
+        let merged = labels.merge(&[("thread", b"0"), ("rcode", b"NOERROR")]);
+        assert_eq!(
+            merged,
+            vec![
+                ("site", &b"fra1"[..]),
+                ("thread", &b"0"[..]),
+                ("rcode", &b"NOERROR"[..])
+            ]
+        );
+    }
+
+    #[test]
+    fn test_union() {
+        let labels = Labels::new().with("site", "ams1").with("role", "edge");
+        let union = labels.union(&Labels::new().with("instance", "a").with("site", "fra1"));
+        assert_eq!(
+            union.iter().collect::<Vec<_>>(),
+            vec![("site", &b"fra1"[..]), ("role", &b"edge"[..]), ("instance", &b"a"[..])]
+        );
+    }
+
+    #[test]
+    fn test_is_valid_name() {
+        assert!(Labels::is_valid_name("site"));
+        assert!(Labels::is_valid_name("_site_2"));
+        assert!(!Labels::is_valid_name(""));
+        assert!(!Labels::is_valid_name("2site"));
+        assert!(!Labels::is_valid_name("site-name"));
+        assert!(!Labels::is_valid_name("__name__"));
+    }
+}
@@ -13,88 +13,457 @@
 //!
 //! Since Prometheus text format is quite simple, it is easier to re-implement it
 //! and do a quick and dirty writes directly into the output buffer.
-
-use std::io;
-
+//!
+//! Samples are buffered per metric family, so all samples of the family are written together
+//! no matter in which order they were observed, as the OpenMetrics format requires.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::mem;
+use std::str;
+use std::time::Duration;
+
+mod descriptor;
+mod filter;
+mod labels;
 mod observe;
+mod options;
+mod passthrough;
+mod profile;
+mod protobuf;
 mod value;
 
-use self::value::MetricValue;
+pub use self::descriptor::{Descriptor, MetricKind, Origin};
+pub use self::filter::Filter;
+pub use self::labels::Labels;
+use self::labels::NO_LABELS;
+pub use self::options::{Format, LabelLimit, LabelSelection, MeasurementOptions, ThreadSeries, Window};
+pub use self::profile::Profile;
+use self::value::{Escaped, MetricValue, NumericValue};
+
+/// Prefix of all metric names unless configured otherwise.
+const DEFAULT_NAMESPACE: &str = "unbound_";
 
 #[must_use]
-#[derive(Default)]
-pub struct Measurement(Vec<u8>);
+pub struct Measurement {
+    format: Format,
+    // Prefix of all metric names, including the trailing underscore (if any)
+    namespace: Cow<'static, str>,
+    // Time (since UNIX epoch) when counters were started from zero,
+    // exported as the `_created` samples in the OpenMetrics format and as the created timestamps in protobuf
+    created: Option<Duration>,
+    // Labels added to every series
+    labels: Labels,
+    filter: Filter,
+    profile: Profile,
+    families: Vec<Family>,
+    // Index in the `families` by the metric name it was requested with
+    index: HashMap<Cow<'static, str>, usize>,
+    capacity: usize,
+}
+
+struct Family {
+    name: Cow<'static, str>,
+    // Metric kind and help are needed for the protobuf format only,
+    // text formats are writing them into the buffer along with the first sample
+    kind: &'static str,
+    help: Cow<'static, str>,
+    buffer: Vec<u8>,
+    // Family is denied by the filter or profile, its samples are not formatted at all
+    excluded: bool,
+    // Excluded family or deprecated duplicate of another family, which is not written to the output
+    skip: bool,
+}
+
+impl Default for Measurement {
+    fn default() -> Self {
+        Self {
+            format: Format::default(),
+            namespace: Cow::Borrowed(DEFAULT_NAMESPACE),
+            created: None,
+            labels: Labels::default(),
+            filter: Filter::default(),
+            profile: Profile::default(),
+            families: Vec::new(),
+            index: HashMap::new(),
+            capacity: 0,
+        }
+    }
+}
 
 impl Measurement {
     pub fn with_buffer_capacity(capacity: usize) -> Self {
-        Self(Vec::with_capacity(capacity))
+        Self {
+            capacity,
+            ..Default::default()
+        }
     }
 
-    pub fn counter(&mut self, name: &'static str, help: &'static str) -> MetricGuard<'_, Vec<u8>> {
-        MetricGuard::new(&mut self.0, name, "counter", help)
+    /// Render metrics in the exposition `format` given instead of the Prometheus text one.
+    pub fn with_format(mut self, format: Format) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Prefix metric names with the `namespace` (ex. `dns_resolver_` or an empty one) instead of `unbound_`.
+    ///
+    /// Namespace should be either empty or a valid metric name, which is checked when samples are written.
+    pub fn with_namespace<N>(mut self, namespace: N) -> Self
+    where
+        N: Into<Cow<'static, str>>,
+    {
+        self.namespace = namespace.into();
+        self
+    }
+
+    /// Render only the families allowed by the `filter`.
+    pub fn with_filter(mut self, filter: Filter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Render only the families exported in the naming `profile`.
+    pub fn with_profile(mut self, profile: Profile) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    /// Add the constant `labels` to every series.
+    pub fn with_labels(mut self, labels: Labels) -> Self {
+        self.labels = labels;
+        self
+    }
+
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    pub fn counter<N, H>(&mut self, name: N, help: H) -> MetricGuard<'_, Vec<u8>>
+    where
+        N: Into<Cow<'static, str>>,
+        H: Into<Cow<'static, str>>,
+    {
+        let created = self.created_for_format();
+        self.metric(name.into(), "counter", help.into(), created)
     }
 
-    pub fn gauge(&mut self, name: &'static str, help: &'static str) -> MetricGuard<'_, Vec<u8>> {
-        MetricGuard::new(&mut self.0, name, "gauge", help)
+    pub fn gauge<N, H>(&mut self, name: N, help: H) -> MetricGuard<'_, Vec<u8>>
+    where
+        N: Into<Cow<'static, str>>,
+        H: Into<Cow<'static, str>>,
+    {
+        self.metric(name.into(), "gauge", help.into(), None)
     }
 
     pub fn histogram(&mut self, name: &'static str, help: &'static str) -> HistogramGuard<'_, Vec<u8>> {
-        HistogramGuard::new(&mut self.0, name, help)
+        let format = self.format;
+        let created = self.created_for_format();
+        let (idx, is_new, excluded) = self.family(
+            Cow::Borrowed(name),
+            Cow::Borrowed(name),
+            "histogram",
+            Cow::Borrowed(help),
+        );
+
+        HistogramGuard {
+            w: &mut self.families[idx].buffer,
+            name,
+            header: if is_new { Some(help) } else { None },
+            format,
+            created,
+            pending: Vec::new(),
+            constant: &self.labels,
+            namespace: &self.namespace,
+            excluded,
+        }
+    }
+
+    /// Counter or gauge of the described family `name`.
+    ///
+    /// Fails with `io::ErrorKind::InvalidInput` if the family is not described or it is a histogram,
+    /// see `Measurement::described_histogram`.
+    pub fn described(&mut self, name: &str) -> io::Result<MetricGuard<'_, Vec<u8>>> {
+        let descriptor = Self::descriptor(name)?;
+        match descriptor.kind {
+            MetricKind::Counter => Ok(self.counter(descriptor.name, descriptor.help)),
+            MetricKind::Gauge => Ok(self.gauge(descriptor.name, descriptor.help)),
+            MetricKind::Histogram => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Metric family '{}' is a histogram", name),
+            )),
+        }
+    }
+
+    /// Histogram of the described family `name`.
+    ///
+    /// Fails with `io::ErrorKind::InvalidInput` if the family is not described or it is not a histogram.
+    pub fn described_histogram(&mut self, name: &str) -> io::Result<HistogramGuard<'_, Vec<u8>>> {
+        let descriptor = Self::descriptor(name)?;
+        match descriptor.kind {
+            MetricKind::Histogram => Ok(self.histogram(descriptor.name, descriptor.help)),
+            MetricKind::Counter | MetricKind::Gauge => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Metric family '{}' is not a histogram", name),
+            )),
+        }
     }
 
     pub fn drain(self) -> Vec<u8> {
-        self.0
+        let mut output = Vec::with_capacity(self.capacity);
+        for family in self.families.iter().filter(|family| !family.skip) {
+            match self.format {
+                // Family without samples should not be written at all
+                Format::Protobuf if !family.buffer.is_empty() => protobuf::family(
+                    &mut output,
+                    &format!("{}{}", self.namespace, family.name),
+                    &family.help,
+                    family.kind,
+                    &family.buffer,
+                ),
+                Format::Protobuf => {}
+                _ => output.extend_from_slice(&family.buffer),
+            }
+        }
+        if self.format == Format::OpenMetrics {
+            output.extend_from_slice(b"# EOF\n");
+        }
+
+        output
+    }
+
+    fn descriptor(name: &str) -> io::Result<&'static Descriptor> {
+        Descriptor::find(name).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Metric family '{}' is not described", name),
+            )
+        })
+    }
+
+    fn created_for_format(&self) -> Option<Duration> {
+        match self.format {
+            Format::OpenMetrics | Format::Protobuf => self.created,
+            Format::Prometheus => None,
+        }
+    }
+
+    fn metric(
+        &mut self,
+        name: Cow<'static, str>,
+        kind: &'static str,
+        help: Cow<'static, str>,
+        created: Option<Duration>,
+    ) -> MetricGuard<'_, Vec<u8>> {
+        let format = self.format;
+        let (family, samples) = match (format, kind) {
+            // OpenMetrics counter family is named without the `_total` suffix, but its samples are having it
+            (Format::OpenMetrics, "counter") => {
+                let family = match &name {
+                    Cow::Borrowed(name) => Cow::Borrowed(name.strip_suffix("_total").unwrap_or(name)),
+                    Cow::Owned(name) => Cow::Owned(name.strip_suffix("_total").unwrap_or(name).to_string()),
+                };
+                let samples = Cow::Owned(format!("{}_total", family));
+                (family, samples)
+            }
+            _ => (name.clone(), name.clone()),
+        };
+        let (idx, is_new, excluded) = self.family(name, family.clone(), kind, help.clone());
+
+        MetricGuard {
+            w: &mut self.families[idx].buffer,
+            family,
+            name: samples,
+            kind,
+            header: if is_new { Some((kind, help)) } else { None },
+            format,
+            created,
+            constant: &self.labels,
+            namespace: &self.namespace,
+            excluded,
+        }
+    }
+
+    /// Find or create the buffer for the metric `name` samples, returns its index,
+    /// `true` if it was just created and `true` if it is excluded by the filter or profile.
+    fn family(
+        &mut self,
+        name: Cow<'static, str>,
+        family: Cow<'static, str>,
+        kind: &'static str,
+        help: Cow<'static, str>,
+    ) -> (usize, bool, bool) {
+        let (idx, is_new) = match self.index.get(&name) {
+            Some(idx) => (*idx, false),
+            None => {
+                let excluded = !self.filter.allows(&name) || !self.profile.exports(&name);
+                // Deprecated `foo` duplicate of the `foo_total` counter is the same OpenMetrics family,
+                // so only the first one of them is written
+                let duplicate = self.families.iter().any(|known| known.name == family && !known.skip);
+                self.families.push(Family {
+                    name: family,
+                    kind,
+                    help,
+                    buffer: if excluded {
+                        Vec::new()
+                    } else {
+                        Vec::with_capacity(self.capacity / 64)
+                    },
+                    excluded,
+                    skip: excluded || duplicate,
+                });
+                let _ = self.index.insert(name, self.families.len() - 1);
+
+                (self.families.len() - 1, true)
+            }
+        };
+
+        (idx, is_new, self.families[idx].excluded)
     }
 }
 
+/// OpenMetrics unit of the metric family, based on its name suffix.
+fn unit(family: &str) -> Option<&'static str> {
+    if family.ends_with("_seconds") {
+        Some("seconds")
+    } else if family.ends_with("_bytes") {
+        Some("bytes")
+    } else {
+        None
+    }
+}
+
+fn write_header<T>(w: &mut T, format: Format, namespace: &str, family: &str, kind: &str, help: &str) -> io::Result<()>
+where
+    T: io::Write,
+{
+    w.write_fmt(format_args!("# TYPE {}{} {}\n", namespace, family, kind))?;
+    if let (Format::OpenMetrics, Some(unit)) = (format, unit(family)) {
+        w.write_fmt(format_args!("# UNIT {}{} {}\n", namespace, family, unit))?;
+    }
+    w.write_fmt(format_args!("# HELP {}{} ", namespace, family))?;
+    Escaped::help(&mut *w, format).write_all(help.as_bytes())?;
+    w.write_all(b"\n")
+}
+
+/// Check if the namespaced metric name and label names are matching
+/// the `[a-zA-Z_:][a-zA-Z0-9_:]*` and `[a-zA-Z_][a-zA-Z0-9_]*` regexes.
+///
+/// Names are coming from the exporter itself, so invalid one is a bug and fails the whole measurement
+/// instead of producing the output which Prometheus would reject anyway.
+fn validate_series(namespace: &str, name: &str, labels: &[(&str, &[u8])]) -> io::Result<()> {
+    let mut bytes = namespace.bytes().chain(name.bytes());
+    let starts_valid = matches!(bytes.next(), Some(b) if b.is_ascii_alphabetic() || b == b'_' || b == b':');
+    if !starts_valid || !bytes.all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b':') {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid metric name '{}{}'", namespace, name),
+        ));
+    }
+
+    match labels.iter().find(|(key, _)| !Labels::is_valid_name(key)) {
+        Some((key, _)) => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid label name '{}' of the '{}{}' metric", key, namespace, name),
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Write the `{namespace}{name}{suffix}{label="value",...}` series name of the sample.
+fn write_series<T>(w: &mut T, namespace: &str, name: &str, suffix: &str, labels: &[(&str, &[u8])]) -> io::Result<()>
+where
+    T: io::Write,
+{
+    w.write_fmt(format_args!("{}{}{}", namespace, name, suffix))?;
+    for (idx, (key, value)) in labels.iter().enumerate() {
+        w.write_all(if idx == 0 { b"{" } else { b"," })?;
+        w.write_all(key.as_bytes())?;
+        w.write_all(b"=\"")?;
+        Escaped::label_value(&mut *w).write_all(value)?;
+        w.write_all(b"\"")?;
+    }
+    if !labels.is_empty() {
+        w.write_all(b"}")?;
+    }
+
+    Ok(())
+}
+
 #[must_use]
 pub struct MetricGuard<'t, T>
 where
     T: io::Write + 't,
 {
     w: &'t mut T,
-    name: &'static str,
+    family: Cow<'static, str>,
+    // Samples name, it is different from the family name for OpenMetrics counters only
+    name: Cow<'static, str>,
+    kind: &'static str,
     // metric kind and help text
-    header: Option<(&'static str, &'static str)>,
+    header: Option<(&'static str, Cow<'static, str>)>,
+    format: Format,
+    // Value of the `_created` samples, if they should be written
+    created: Option<Duration>,
+    // Labels added to every sample
+    constant: &'t Labels,
+    namespace: &'t str,
+    // Family is denied by the filter, samples are silently dropped
+    excluded: bool,
 }
 
 impl<'t, T> MetricGuard<'t, T>
 where
     T: io::Write + 't,
 {
-    pub fn new(w: &'t mut T, name: &'static str, kind: &'static str, help: &'static str) -> Self {
+    pub fn new<N, H>(w: &'t mut T, name: N, kind: &'static str, help: H) -> Self
+    where
+        N: Into<Cow<'static, str>>,
+        H: Into<Cow<'static, str>>,
+    {
+        let name = name.into();
         Self {
             w,
+            family: name.clone(),
             name,
-            header: Some((kind, help)),
+            kind,
+            header: Some((kind, help.into())),
+            format: Format::Prometheus,
+            created: None,
+            constant: &NO_LABELS,
+            namespace: DEFAULT_NAMESPACE,
+            excluded: false,
         }
     }
 
     pub fn set<V>(&mut self, value: V) -> io::Result<&mut Self>
     where
-        V: MetricValue,
+        V: NumericValue,
     {
-        self.ensure_header()?;
-
-        self.w.write_fmt(format_args!("unbound_{} ", self.name))?;
-        value.write(&mut self.w)?;
-        self.w.write_all(b"\n")?;
+        self.write_sample(&[], value)?;
 
         Ok(self)
     }
 
-    pub fn set_with_label<L, V>(&mut self, key: &'static str, label: L, value: V) -> io::Result<&mut Self>
+    pub fn set_with_label<L, V>(&mut self, key: &str, label: L, value: V) -> io::Result<&mut Self>
     where
         L: MetricValue,
-        V: MetricValue,
+        V: NumericValue,
     {
-        self.ensure_header()?;
+        let mut label_value = Vec::with_capacity(16);
+        label.write(&mut label_value)?;
+        self.write_sample(&[(key, &label_value)], value)?;
 
-        self.w.write_fmt(format_args!("unbound_{}{{{}=\"", self.name, key))?;
-        label.write(&mut self.w)?;
-        self.w.write_all(b"\"} ")?;
-        value.write(&mut self.w)?;
-        self.w.write_all(b"\n")?;
+        Ok(self)
+    }
+
+    /// Write the sample with any number of labels, ex. `{thread="0",rcode="NOERROR"}`.
+    pub fn set_with_labels<V>(&mut self, labels: &Labels, value: V) -> io::Result<&mut Self>
+    where
+        V: NumericValue,
+    {
+        let labels = labels.iter().collect::<Vec<_>>();
+        self.write_sample(&labels, value)?;
 
         Ok(self)
     }
@@ -107,14 +476,43 @@ where
         self
     }
 
+    fn write_sample<V>(&mut self, labels: &[(&str, &[u8])], value: V) -> io::Result<()>
+    where
+        V: NumericValue,
+    {
+        if self.excluded {
+            return Ok(());
+        }
+
+        let labels = self.constant.merge(labels);
+        validate_series(self.namespace, &self.name, &labels)?;
+
+        if self.format == Format::Protobuf {
+            let mut metric = Vec::with_capacity(64);
+            protobuf::sample(&mut metric, self.kind, &labels, value.to_f64(), self.created);
+            return self.w.write_all(&metric);
+        }
+
+        self.ensure_header()?;
+
+        write_series(&mut self.w, self.namespace, &self.name, "", &labels)?;
+        self.w.write_all(b" ")?;
+        value.write(&mut self.w)?;
+        self.w.write_all(b"\n")?;
+
+        if let Some(created) = self.created {
+            write_series(&mut self.w, self.namespace, &self.family, "_created", &labels)?;
+            self.w.write_all(b" ")?;
+            created.write(&mut self.w)?;
+            self.w.write_all(b"\n")?;
+        }
+
+        Ok(())
+    }
+
     fn ensure_header(&mut self) -> io::Result<()> {
         match self.header.take() {
-            Some((kind, help)) => self.w.write_fmt(format_args!(
-                "# TYPE unbound_{name} {kind}\n# HELP unbound_{name} {help}\n",
-                name = self.name,
-                kind = kind,
-                help = help
-            )),
+            Some((kind, help)) => write_header(&mut self.w, self.format, self.namespace, &self.family, kind, &help),
             None => Ok(()),
         }
     }
@@ -129,6 +527,16 @@ where
     name: &'static str,
     // metric help text
     header: Option<&'static str>,
+    format: Format,
+    // Value of the `_created` sample, if it should be written
+    created: Option<Duration>,
+    // Protobuf `Histogram` message fields, which are written at once by the `count` call
+    pending: Vec<u8>,
+    // Labels added to every sample
+    constant: &'t Labels,
+    namespace: &'t str,
+    // Family is denied by the filter, samples are silently dropped
+    excluded: bool,
 }
 
 impl<'t, T> HistogramGuard<'t, T>
@@ -140,58 +548,109 @@ where
             w,
             name,
             header: Some(help),
+            format: Format::Prometheus,
+            created: None,
+            pending: Vec::new(),
+            constant: &NO_LABELS,
+            namespace: DEFAULT_NAMESPACE,
+            excluded: false,
         }
     }
 
     pub fn bucket<L, V>(&mut self, le: L, value: V) -> io::Result<&mut Self>
     where
         L: MetricValue,
-        V: MetricValue,
+        V: NumericValue,
     {
-        self.ensure_header()?;
+        if self.excluded {
+            return Ok(self);
+        }
 
-        self.w.write_fmt(format_args!("unbound_{}_bucket{{le=\"", self.name))?;
-        le.write(&mut self.w)?;
-        self.w.write_all(b"\"} ")?;
-        value.write(&mut self.w)?;
-        self.w.write_all(b"\n")?;
+        let mut upper_bound = Vec::with_capacity(16);
+        le.write(&mut upper_bound)?;
+
+        if self.format == Format::Protobuf {
+            // `+Inf` bucket is implied by the sample count
+            if upper_bound != b"+Inf" {
+                let upper_bound = str::from_utf8(&upper_bound)
+                    .ok()
+                    .and_then(|upper_bound| upper_bound.parse::<f64>().ok())
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid bucket upper bound"))?;
+                protobuf::histogram_bucket(&mut self.pending, upper_bound, value.to_f64() as u64);
+            }
+            return Ok(self);
+        }
+
+        self.write_sample("_bucket", &[("le", &upper_bound)], value)?;
 
         Ok(self)
     }
 
     pub fn sum<V>(&mut self, value: V) -> io::Result<&mut Self>
     where
-        V: MetricValue,
+        V: NumericValue,
     {
-        self.ensure_header()?;
+        if self.excluded {
+            return Ok(self);
+        }
 
-        self.w.write_fmt(format_args!("unbound_{}_sum ", self.name))?;
-        value.write(&mut self.w)?;
-        self.w.write_all(b"\n")?;
+        if self.format == Format::Protobuf {
+            protobuf::histogram_sum(&mut self.pending, value.to_f64());
+            return Ok(self);
+        }
+
+        self.write_sample("_sum", &[], value)?;
 
         Ok(self)
     }
 
+    /// Should be called after all buckets were written, as it finishes the histogram:
+    /// `_created` sample follows the `_count` one in the OpenMetrics format
+    /// and protobuf message is written only with it.
     pub fn count<V>(&mut self, value: V) -> io::Result<&mut Self>
     where
-        V: MetricValue,
+        V: NumericValue,
     {
-        self.ensure_header()?;
+        if self.excluded {
+            return Ok(self);
+        }
 
-        self.w.write_fmt(format_args!("unbound_{}_count ", self.name))?;
-        value.write(&mut self.w)?;
-        self.w.write_all(b"\n")?;
+        if self.format == Format::Protobuf {
+            let labels = self.constant.merge(&[]);
+            validate_series(self.namespace, self.name, &labels)?;
+            let mut histogram = mem::take(&mut self.pending);
+            protobuf::histogram_count(&mut histogram, value.to_f64() as u64);
+            let mut metric = Vec::with_capacity(histogram.len() + 16);
+            protobuf::histogram(&mut metric, histogram, &labels, self.created);
+            self.w.write_all(&metric)?;
+            return Ok(self);
+        }
+
+        self.write_sample("_count", &[], value)?;
+        if let Some(created) = self.created {
+            self.write_sample("_created", &[], created)?;
+        }
 
         Ok(self)
     }
 
+    fn write_sample<V>(&mut self, suffix: &str, labels: &[(&str, &[u8])], value: V) -> io::Result<()>
+    where
+        V: NumericValue,
+    {
+        let labels = self.constant.merge(labels);
+        validate_series(self.namespace, self.name, &labels)?;
+        self.ensure_header()?;
+
+        write_series(&mut self.w, self.namespace, self.name, suffix, &labels)?;
+        self.w.write_all(b" ")?;
+        value.write(&mut self.w)?;
+        self.w.write_all(b"\n")
+    }
+
     fn ensure_header(&mut self) -> io::Result<()> {
         match self.header.take() {
-            Some(help) => self.w.write_fmt(format_args!(
-                "# TYPE unbound_{name} histogram\n# HELP unbound_{name} {help}\n",
-                name = self.name,
-                help = help
-            )),
+            Some(help) => write_header(&mut self.w, self.format, self.namespace, self.name, "histogram", help),
             None => Ok(()),
         }
     }
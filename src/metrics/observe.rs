@@ -1,131 +1,151 @@
+use std::collections::HashSet;
 use std::io;
+use std::mem;
 
-use super::Measurement;
-use crate::statistics::{Bucket, Statistics};
+use domain::base::iana::Rcode;
+
+use super::value::MetricValue;
+use super::{LabelLimit, LabelSelection, Labels, Measurement, MeasurementOptions, ThreadSeries, Window};
+use crate::statistics::{Bucket, Statistics, Thread};
 
 impl Measurement {
-    #[allow(unused_results)] // `.set` and `.set_with_label` are returning a lot of references.
-    pub fn observe(mut s: Statistics) -> io::Result<Self> {
+    pub fn observe(s: Statistics) -> io::Result<Self> {
+        Self::observe_with(s, &MeasurementOptions::default())
+    }
+
+    pub fn observe_with(s: Statistics, options: &MeasurementOptions) -> io::Result<Self> {
+        Self::observe_since(s, None, options)
+    }
+
+    /// Same as `Measurement::observe_with`, but `Window::Interval` metrics are computed
+    /// for the time passed since the `previous` statistics of the same source were observed.
+    pub fn observe_since(
+        s: Statistics,
+        previous: Option<&Statistics>,
+        options: &MeasurementOptions,
+    ) -> io::Result<Self> {
+        Self::observe_selected(s, previous, &mut LabelSelection::new(), options)
+    }
+
+    /// Same as `Measurement::observe_since`, but the `LabelLimit::Top` label values
+    /// are kept in the `selection` of the same source between scrapes.
+    pub fn observe_selected(
+        s: Statistics,
+        previous: Option<&Statistics>,
+        selection: &mut LabelSelection,
+        options: &MeasurementOptions,
+    ) -> io::Result<Self> {
         // Roughly equal to the response body size plus some extra capacity
-        let mut w = Measurement::with_buffer_capacity(16_834);
+        let mut w = Measurement::with_buffer_capacity(16_834)
+            .with_format(options.format)
+            .with_labels(options.labels.clone())
+            .with_filter(options.filter.clone())
+            .with_profile(options.profile);
+        if let Some(namespace) = &options.namespace {
+            w = w.with_namespace(namespace.clone());
+        }
+        w.observe_statistics(s, previous, selection, options)?;
+
+        Ok(w)
+    }
+
+    /// Add the series of one more statistics snapshot (ex. of a pool member) to the same families,
+    /// distinguished by the extra `labels` (ex. `member="10.0.0.1:8953"`).
+    pub fn observe_labeled(
+        &mut self,
+        s: Statistics,
+        previous: Option<&Statistics>,
+        selection: &mut LabelSelection,
+        options: &MeasurementOptions,
+        labels: &Labels,
+    ) -> io::Result<()> {
+        let constant = mem::replace(&mut self.labels, options.labels.union(labels));
+        let created = self.created.take();
+        let result = self.observe_statistics(s, previous, selection, options);
+        self.labels = constant;
+        self.created = created;
+
+        result
+    }
+
+    #[allow(unused_results)] // `.set` and `.set_with_label` are returning a lot of references.
+    fn observe_statistics(
+        &mut self,
+        mut s: Statistics,
+        previous: Option<&Statistics>,
+        selection: &mut LabelSelection,
+        options: &MeasurementOptions,
+    ) -> io::Result<()> {
+        let w = self;
+        // Counters are starting from zero at the `unbound` start
+        if s.time.now > s.time.up {
+            w.created = Some(s.time.now - s.time.up);
+        }
 
         // Common
-        w.gauge("num_threads", "The number of threads to create to serve clients")
-            .set(s.threads.len())?;
+        w.described("num_threads")?.set(s.threads.len())?;
 
         // Time
-        w.counter("time_up_seconds_total", "Uptime since server boot in seconds")
-            .set(s.time.up)?;
-        w.counter("time_now_seconds", "Current time in seconds since UNIX epoch")
-            .set(s.time.now)?;
-        w.counter("time_elapsed_seconds", "Time since last statistics printout in seconds")
-            .set(s.time.elapsed)?;
+        w.described("time_up_seconds_total")?.set(s.time.up)?;
+        w.described("time_now_seconds")?.set(s.time.now)?;
+        w.described("time_elapsed_seconds")?.set(s.time.elapsed)?;
 
         // Memory caches
-        w.gauge("memory_caches_bytes", "Memory in bytes in use by caches")
+        w.described("memory_caches_bytes")?
             .set_with_label("cache", "rrset", s.cache.rrset)?
             .set_with_label("cache", "message", s.cache.message)?
             .set_with_label("cache", "dnscrypt_shared_secret", s.cache.dnscrypt_shared_secret)?
             .set_with_label("cache", "dnscrypt_nonce", s.cache.dnscrypt_nonce)?;
 
         // Memory modules
-        w.gauge("memory_modules_bytes", "Memory in bytes in use by modules")
+        w.described("memory_modules_bytes")?
             .set_with_label("module", "iterator", s.modules.iterator)?
             .set_with_label("module", "validator", s.modules.validator)?
             .set_with_label("module", "respip", s.modules.respip)?
-            .set_with_label("module", "subnet", s.modules.subnet)?;
-        // TODO:
-        // .set_with_label("module", "ipsecmod", s.modules.ipsecmod)?
+            .set_with_label("module", "subnet", s.modules.subnet)?
+            .set_with_label("module", "ipsecmod", s.modules.ipsecmod)?
+            .set_with_label("module", "dynlibmod", s.modules.dynlibmod)?
+            .set_with_label("module", "cachedb", s.modules.cachedb)?;
+
+        w.described("module_queries_total")?
+            .set_with_label("module", "subnet", s.num_query_subnet)?
+            .set_with_label("module", "subnet_cache", s.num_query_subnet_cache)?
+            .set_with_label("module", "cachedb", s.num_query_cachedb)?;
 
-        w.gauge("memory_http_bytes", "Memory in bytes in use by HTTP/2 queries")
+        w.described("memory_http_bytes")?
             .set_with_label("http", "query_buffer", s.http.query_buffer)?
             .set_with_label("http", "response_buffer", s.http.response_buffer)?;
 
         // Mem buffers
-        w.gauge(
-            "memory_stream_wait_count",
-            "The number of bytes in the stream wait buffers",
-        )
-        .set(s.mem_streamwait)?;
+        w.described("memory_stream_wait_count")?.set(s.mem_streamwait)?;
 
-        w.counter(
-            "query_tcp_total",
-            "Total number of queries that were made using TCP towards the server",
-        )
-        .set(s.num_query_tcp)?;
-        w.counter(
-            "query_tcp_out_total",
-            "Total number of queries that were made using TCP outwards the server",
-        )
-        .set(s.num_query_tcp_out)?;
-        w.counter(
-            "query_tls_total",
-            "Total number of queries that were made using TLS towards the server",
-        )
-        .set(s.num_query_tls)?;
-        w.counter(
-            "query_tls_resume_total",
-            "Total number of queries that were made using TLS resumption",
-        )
-        .set(s.num_query_tls_resume)?;
-        w.counter(
-            "query_ipv6_total",
-            "Total number of queries that were made using IPv6 toward the server",
-        )
-        .set(s.num_query_ipv6)?;
-        w.counter(
-            "query_https_total",
-            "Total number of queries that were made using HTTPS",
-        )
-        .set(s.num_query_https)?;
+        w.described("query_tcp_total")?.set(s.num_query_tcp)?;
+        w.described("query_tcp_out_total")?.set(s.num_query_tcp_out)?;
+        w.described("query_tls_total")?.set(s.num_query_tls)?;
+        w.described("query_tls_resume_total")?.set(s.num_query_tls_resume)?;
+        w.described("query_ipv6_total")?.set(s.num_query_ipv6)?;
+        w.described("query_https_total")?.set(s.num_query_https)?;
 
         // Query EDNS numbers
-        w.counter(
-            "query_edns_DO_total",
-            "Total number of queries that had an EDNS OPT record with the DO (DNSSEC OK) bit set present",
-        )
-        .set(s.num_query_edns_do)?;
-        w.counter(
-            "query_edns_present_total",
-            "Total number of queries that had an EDNS OPT record present",
-        )
-        .set(s.num_query_edns_present)?;
+        w.described("query_edns_DO_total")?.set(s.num_query_edns_do)?;
+        w.described("query_edns_present_total")?.set(s.num_query_edns_present)?;
         // Query iteration numbers
-        w.counter(
-            "query_ratelimited_total",
-            "Total number of queries that had been rate limited",
-        )
-        .set(s.num_query_rate_limited)?;
+        w.described("query_ratelimited_total")?.set(s.num_query_rate_limited)?;
 
         // Query validation numbers
-        w.counter("answers_secure_total", "Total amount of answers that were secure (AD)")
-            .set(s.num_answer_secure)?;
+        w.described("answers_secure_total")?.set(s.num_answer_secure)?;
         // Deprecated version to maintain compatibility
-        w.counter(
-            "answers_bogus",
-            "Total amount of answers that were bogus (withheld as SERVFAIL)",
-        )
-        .set(s.num_answer_bogus)?;
-        w.counter(
-            "answers_bogus_total",
-            "Total amount of answers that were bogus (withheld as SERVFAIL)",
-        )
-        .set(s.num_answer_bogus)?;
+        w.described("answers_bogus")?.set(s.num_answer_bogus)?;
+        w.described("answers_bogus_total")?.set(s.num_answer_bogus)?;
 
-        w.counter(
-            "rrset_bogus_total",
-            "Total number of rrsets marked bogus by the validator",
-        )
-        .set(s.num_rrset_bogus)?;
+        w.described("rrset_bogus_total")?.set(s.num_rrset_bogus)?;
 
         // Cache count (deprecated, exposed only to maintain compatibility with `kumina/unbound_exporter`)
-        w.gauge("msg_cache_count", "The number of messages cached")
-            .set(s.cache_count.message)?;
-        w.gauge("rrset_cache_count", "The number of rrset cached")
-            .set(s.cache_count.rrset)?;
+        w.described("msg_cache_count")?.set(s.cache_count.message)?;
+        w.described("rrset_cache_count")?.set(s.cache_count.rrset)?;
 
         // Cache count (new version)
-        w.gauge("cache_count_total", "The number of cached entries")
+        w.described("cache_count_total")?
             .set_with_label("type", "message", s.cache_count.message)?
             .set_with_label("type", "rrset", s.cache_count.rrset)?
             .set_with_label("type", "key", s.cache_count.key)?
@@ -133,186 +153,853 @@ impl Measurement {
             .set_with_label("type", "dnscrypt_nonce", s.cache_count.dnscrypt_nonce)?
             .set_with_label("type", "dnscrypt_shared_secret", s.cache_count.dnscrypt_shared_secret)?;
 
-        w.counter(
-            "unwanted_queries_total",
-            "Total number of queries that were refused or dropped because they failed the access control settings.",
-        )
-        .set(s.num_unwanted_queries)?;
-        w.counter(
-            "unwanted_replies_total",
-            "Total number of replies that were unwanted or unsolicited",
-        )
-        .set(s.num_unwanted_replies)?;
+        w.described("unwanted_queries_total")?.set(s.num_unwanted_queries)?;
+        w.described("unwanted_replies_total")?.set(s.num_unwanted_replies)?;
 
-        let mut answer_rcodes = w.counter(
-            "answer_rcodes_total",
-            "Total number of answers to queries, from cache or from recursion, by response code.",
-        );
+        let mut answer_rcodes = w.described("answer_rcodes_total")?;
         for (rcode, value) in s.answer_rcodes.iter() {
             answer_rcodes.set_with_label("rcode", rcode, value)?;
         }
-        let mut query_opcodes = w.counter(
-            "query_opcodes_total",
-            "Total number of queries with a given query opcode",
-        );
-        for (opcode, value) in s.query_opcodes.iter() {
-            query_opcodes.set_with_label("opcode", opcode, value)?;
+        let mut answer_ede = w.described("answer_ede_total")?;
+        for (code, value) in s.answer_ede.iter() {
+            answer_ede.set_with_label("code", code, value)?;
         }
-        let mut query_types = w.counter("query_types_total", "Total number of queries with a given query type");
-        query_types.set_with_label("type", "other", s.query_types_other)?;
-        for (rtype, value) in s.query_types.iter() {
-            query_types.set_with_label("type", rtype, value)?;
+        let mut rpz_actions = w.described("rpz_action_total")?;
+        for (action, value) in s.rpz_actions.iter() {
+            rpz_actions.set_with_label("action", action, value)?;
         }
-        let mut query_classes = w.counter(
-            "query_classes_total",
-            "Total number of queries with a given query class",
-        );
-        query_classes.set_with_label("class", "other", s.query_classes_other)?;
-        for (class, value) in s.query_classes.iter() {
-            query_classes.set_with_label("class", class, value)?;
+        let opcodes = fold(
+            s.query_opcodes.iter(),
+            &options.query_opcodes_limit,
+            &mut selection.opcodes,
+        )?;
+        let mut query_opcodes = w.described("query_opcodes_total")?;
+        // Unlike types and classes, `unbound` does not report the other opcodes
+        if options.query_opcodes_limit != LabelLimit::Unlimited {
+            query_opcodes.set_with_label("opcode", "other", opcodes.other)?;
         }
-        w.counter(
-            "query_flags_total",
-            "Total number of queries that had a given flag set in the header",
-        )
-        .set_with_label("flag", "QR", s.flags.qr)?
-        .set_with_label("flag", "AA", s.flags.aa)?
-        .set_with_label("flag", "TC", s.flags.tc)?
-        .set_with_label("flag", "RD", s.flags.rd)?
-        .set_with_label("flag", "RA", s.flags.ra)?
-        .set_with_label("flag", "Z", s.flags.z)?
-        .set_with_label("flag", "AD", s.flags.ad)?
-        .set_with_label("flag", "CD", s.flags.cd)?;
+        for (opcode, value) in opcodes.values.iter() {
+            query_opcodes.set_with_label("opcode", *opcode, value)?;
+        }
+        let types = fold(s.query_types.iter(), &options.query_types_limit, &mut selection.types)?;
+        let mut query_types = w.described("query_types_total")?;
+        query_types.set_with_label("type", "other", s.query_types_other.wrapping_add(types.other))?;
+        for (rtype, value) in types.values.iter() {
+            query_types.set_with_label("type", *rtype, value)?;
+        }
+        let classes = fold(
+            s.query_classes.iter(),
+            &options.query_classes_limit,
+            &mut selection.classes,
+        )?;
+        let mut query_classes = w.described("query_classes_total")?;
+        query_classes.set_with_label("class", "other", s.query_classes_other.wrapping_add(classes.other))?;
+        for (class, value) in classes.values.iter() {
+            query_classes.set_with_label("class", *class, value)?;
+        }
+        w.described("exporter_labels_folded")?
+            .set_with_label("label", "type", types.count)?
+            .set_with_label("label", "class", classes.count)?
+            .set_with_label("label", "opcode", opcodes.count)?;
+        w.described("query_flags_total")?
+            .set_with_label("flag", "QR", s.flags.qr)?
+            .set_with_label("flag", "AA", s.flags.aa)?
+            .set_with_label("flag", "TC", s.flags.tc)?
+            .set_with_label("flag", "RD", s.flags.rd)?
+            .set_with_label("flag", "RA", s.flags.ra)?
+            .set_with_label("flag", "Z", s.flags.z)?
+            .set_with_label("flag", "AD", s.flags.ad)?
+            .set_with_label("flag", "CD", s.flags.cd)?;
+
+        w.described("exporter_unknown_keys")?.set(s.unknown.len())?;
+        w.described("exporter_parse_errors")?.set(s.diagnostics.len())?;
 
         // Histogram
-        let mut hist = w.histogram("response_time_seconds", "Query response time in seconds");
-        hist.sum(s.histogram.sum())?.count(s.histogram.count())?;
-        for bucket in s.histogram.buckets() {
+        if !options.quantiles.is_empty() {
+            let interval;
+            let quantiles_histogram = match (options.quantiles_window, previous) {
+                (Window::Lifetime, _) => Some(&s.histogram),
+                (Window::Interval, Some(previous)) => {
+                    interval = s.delta(previous).increase.histogram;
+                    Some(&interval)
+                }
+                (Window::Interval, None) => None,
+            };
+
+            let mut gauge = w.described("response_time_quantile_seconds")?;
+            if let Some(histogram) = quantiles_histogram {
+                for q in options.quantiles.iter() {
+                    if let Some(value) = histogram.quantile(*q) {
+                        gauge.set_with_label("quantile", *q, value)?;
+                    }
+                }
+            }
+        }
+
+        let mut hist = w.described_histogram("response_time_seconds")?;
+        let buckets: Box<dyn Iterator<Item = Bucket>> = match &options.histogram_buckets {
+            Some(bounds) => Box::new(s.histogram.rebucket(bounds).into_iter()),
+            None => Box::new(s.histogram.buckets()),
+        };
+        for bucket in buckets {
             match bucket {
                 Bucket::Le(le, value) => hist.bucket(le, value)?,
                 Bucket::Inf(value) => hist.bucket("+Inf", value)?,
             };
         }
+        hist.sum(s.histogram.sum())?.count(s.histogram.count())?;
+
+        if options.ratios {
+            w.observe_ratios(&s, "lifetime")?;
+            if let Some(previous) = previous {
+                w.observe_ratios(&s.delta(previous).increase, "interval")?;
+            }
+        }
 
         // threads
-        for (idx, thread) in s.threads.iter().enumerate() {
-            let add_header = idx == 0;
-
-            // Queries
-            w.counter("queries_total", "Total number of queries received")
-                .needs_header(add_header)
-                .set_with_label("thread", idx, thread.num_queries)?;
-            w.counter(
-                "queries_ip_ratelimited_total",
-                "Total number of queries rate limited by IP",
-            )
+        let mut threads = Vec::with_capacity(s.threads.len() + 1);
+        if options.thread_series.per_thread() {
+            for (idx, thread) in s.threads.iter().enumerate() {
+                threads.push((thread, Labels::new().with("thread", idx)));
+            }
+        }
+        match options.thread_series {
+            ThreadSeries::PerThreadAndTotal | ThreadSeries::Total => {
+                threads.push((&s.total, Labels::new().with("thread", "total")))
+            }
+            ThreadSeries::Unlabeled => threads.push((&s.total, Labels::new())),
+            ThreadSeries::PerThread => {}
+        }
+        for (idx, (thread, labels)) in threads.iter().enumerate() {
+            w.observe_thread(thread, labels, idx == 0)?;
+        }
+
+        if options.passthrough {
+            w.passthrough(&s.unknown, options.thread_series)?;
+        }
+
+        Ok(())
+    }
+
+    /// Observe the derived ratio gauges of the `s` statistics (or of their increase) for the `window` given.
+    ///
+    /// Ratios with the zero denominator are not exported.
+    #[allow(unused_results)]
+    fn observe_ratios(&mut self, s: &Statistics, window: &'static str) -> io::Result<()> {
+        let total = &s.total;
+        let answers = s
+            .answer_rcodes
+            .values()
+            .fold(0u64, |sum, value| sum.wrapping_add(*value));
+        let servfail = s.answer_rcodes.get(&Rcode::ServFail).copied().unwrap_or_default();
+        let ratios = [
+            (
+                "cache_hit_ratio",
+                total.num_cache_hits,
+                total.num_cache_hits.wrapping_add(total.num_cache_miss),
+            ),
+            ("prefetch_ratio", total.num_prefetch, total.num_queries),
+            ("answers_servfail_ratio", servfail, answers),
+            (
+                "answers_bogus_ratio",
+                s.num_answer_bogus,
+                s.num_answer_secure.wrapping_add(s.num_answer_bogus),
+            ),
+        ];
+        for (name, part, whole) in ratios.iter() {
+            // Family is requested anyway, so the families order does not depend on the values
+            let mut gauge = self.described(name)?;
+            if *whole > 0 {
+                gauge.set_with_label("window", window, *part as f64 / *whole as f64)?;
+            }
+        }
+
+        // Both values are describing the whole `unbound` uptime, so there is no way to compute it for an interval
+        let mut gauge = self.described("request_list_avg_to_max_ratio")?;
+        if window == "lifetime" && total.requestlist_max > 0 {
+            gauge.set_with_label("window", window, total.requestlist_avg / total.requestlist_max as f64)?;
+        }
+
+        Ok(())
+    }
+
+    /// Observe the per-thread families of one `thread` (or of the totals) series with the `labels` given.
+    #[allow(unused_results)]
+    fn observe_thread(&mut self, thread: &Thread, labels: &Labels, add_header: bool) -> io::Result<()> {
+        // Queries
+        self.described("queries_total")?
             .needs_header(add_header)
-            .set_with_label("thread", idx, thread.num_queries_ip_ratelimited)?;
-            w.counter(
-                "cache_hits_total",
-                "Total number of queries that were successfully answered using a cache lookup.",
-            )
+            .set_with_labels(labels, thread.num_queries)?;
+        self.described("queries_ip_ratelimited_total")?
             .needs_header(add_header)
-            .set_with_label("thread", idx, thread.num_cache_hits)?;
-            w.counter(
-                "cache_misses_total",
-                "Total number of cache queries that needed recursive processing.",
-            )
+            .set_with_labels(labels, thread.num_queries_ip_ratelimited)?;
+        self.described("cache_hits_total")?
             .needs_header(add_header)
-            .set_with_label("thread", idx, thread.num_cache_miss)?;
-            w.counter("prefetches_total", "Total number of cache prefetches performed")
-                .needs_header(add_header)
-                .set_with_label("thread", idx, thread.num_prefetch)?;
-
-            // Deprecated since unbound version 1.10.1
-            w.counter(
-                "zero_ttl_responses_total",
-                "Total number of replies with ttl zero, because they served an expired cache entry.",
-            )
+            .set_with_labels(labels, thread.num_cache_hits)?;
+        self.described("cache_misses_total")?
             .needs_header(add_header)
-            .set_with_label("thread", idx, thread.num_zero_ttl)?;
-            // Added since unbound version 1.10.1
-            w.counter(
-                "expired_responses_total",
-                "Total number of replies that served an expired cache entry.",
-            )
+            .set_with_labels(labels, thread.num_cache_miss)?;
+        self.described("prefetches_total")?
             .needs_header(add_header)
-            .set_with_label("thread", idx, thread.num_zero_ttl)?;
+            .set_with_labels(labels, thread.num_prefetch)?;
 
-            // TODO:!
-            //            w.counter("recursive_replies_total","Total number of replies sent to queries that needed recursive processing")
-            //                .set_with_label("thread", idx, thread.mesh_replies_sent)?;
+        // Deprecated since unbound version 1.10.1
+        self.described("zero_ttl_responses_total")?
+            .needs_header(add_header)
+            .set_with_labels(labels, thread.num_zero_ttl)?;
+        // Added since unbound version 1.10.1
+        self.described("expired_responses_total")?
+            .needs_header(add_header)
+            .set_with_labels(labels, thread.num_zero_ttl)?;
 
-            // DNSCrypt
-            w.counter(
-                "dnscrypt_valid_queries_total",
-                "Total number of queries that were encrypted and successfully decapsulated by dnscrypt",
-            )
+        // TODO:!
+        //            w.described("recursive_replies_total")?
+        //                .set_with_labels(labels, thread.mesh_replies_sent)?;
+
+        // DNSCrypt
+        self.described("dnscrypt_valid_queries_total")?
             .needs_header(add_header)
-            .set_with_label("thread", idx, thread.num_dnscrypt_crypted)?;
-            w.counter(
-                "dnscrypt_cert_queries_total",
-                "Total number of queries that were requesting dnscrypt certificates",
-            )
+            .set_with_labels(labels, thread.num_dnscrypt_crypted)?;
+        self.described("dnscrypt_cert_queries_total")?
             .needs_header(add_header)
-            .set_with_label("thread", idx, thread.num_dnscrypt_cert)?;
-            w.counter("dnscrypt_cleartext_queries_total", "Total number of queries received on dnscrypt port that were cleartext and not a request for certificates")
-                .needs_header(add_header)
-                .set_with_label("thread", idx,thread.num_dnscrypt_cleartext)?;
-            w.counter(
-                "dnscrypt_malformed_queries_total",
-                "Total number of requests that were neither cleartext, not valid dnscrypt messages",
-            )
+            .set_with_labels(labels, thread.num_dnscrypt_cert)?;
+        self.described("dnscrypt_cleartext_queries_total")?
             .needs_header(add_header)
-            .set_with_label("thread", idx, thread.num_dnscrypt_malformed)?;
+            .set_with_labels(labels, thread.num_dnscrypt_cleartext)?;
+        self.described("dnscrypt_malformed_queries_total")?
+            .needs_header(add_header)
+            .set_with_labels(labels, thread.num_dnscrypt_malformed)?;
 
-            // Request list
-            w.gauge(
-                "request_list_current_all",
-                "Current size of the request list, including internally generated queries",
-            )
+        // Request list
+        self.described("request_list_current_all")?
             .needs_header(add_header)
-            .set_with_label("thread", idx, thread.requestlist_current_all)?;
-            w.gauge(
-                "request_list_current_user",
-                "Current size of the request list, only counting the requests from client queries",
-            )
+            .set_with_labels(labels, thread.requestlist_current_all)?;
+        self.described("request_list_current_user")?
             .needs_header(add_header)
-            .set_with_label("thread", idx, thread.requestlist_current_user)?;
-            // TODO:
-            w.counter(
-                "request_list_exceeded_total",
-                "Number of queries that were dropped because the request list was full",
-            )
+            .set_with_labels(labels, thread.requestlist_current_user)?;
+        // TODO:
+        self.described("request_list_exceeded_total")?
             .needs_header(add_header)
-            .set_with_label("thread", idx, thread.requestlist_exceeded)?;
-            w.counter(
-                "request_list_overwritten_total",
-                "Total number of requests in the request list that were overwritten by newer entries",
-            )
+            .set_with_labels(labels, thread.requestlist_exceeded)?;
+        self.described("request_list_overwritten_total")?
             .needs_header(add_header)
-            .set_with_label("thread", idx, thread.requestlist_overwritten)?;
-
-            // Recursion
-            w.gauge("recursion_time_seconds_avg", "Average time it took to answer queries that needed recursive processing (does not include in-cache requests)")
-                .needs_header(add_header)
-                .set_with_label("thread", idx, thread.recursion_time_avg)?;
-            w.gauge(
-                "recursion_time_seconds_median",
-                "The median of the time it took to answer queries that needed recursive processing",
-            )
+            .set_with_labels(labels, thread.requestlist_overwritten)?;
+
+        // Recursion
+        self.described("recursion_time_seconds_avg")?
+            .needs_header(add_header)
+            .set_with_labels(labels, thread.recursion_time_avg)?;
+        self.described("recursion_time_seconds_median")?
             .needs_header(add_header)
-            .set_with_label("thread", idx, thread.recursion_time_median)?;
+            .set_with_labels(labels, thread.recursion_time_median)?;
 
-            // TCP usage
-            w.gauge(
-                "tcp_usage_current",
-                "Number of the currently held TCP buffers for incoming connections",
-            )
+        // TCP usage
+        self.described("tcp_usage_current")?
             .needs_header(add_header)
-            .set_with_label("thread", idx, thread.tcp_usage)?;
+            .set_with_labels(labels, thread.tcp_usage)?;
+
+        Ok(())
+    }
+}
+
+/// Counters split by the label limit.
+struct Folded<K> {
+    /// Exported counters
+    values: Vec<(K, u64)>,
+    /// Sum of the folded counters
+    other: u64,
+    /// Number of the folded label values
+    count: usize,
+}
+
+/// Split counters by the `limit`; `LabelLimit::Top` values are added to the `selected` ones
+/// until there are `k` of them, which are exported on every scrape.
+fn fold<'a, K, I>(values: I, limit: &LabelLimit, selected: &mut HashSet<Vec<u8>>) -> io::Result<Folded<K>>
+where
+    I: Iterator<Item = (&'a K, &'a u64)>,
+    K: MetricValue + Copy + 'a,
+{
+    // Label values are rendered, so they can be compared with the allowed ones and sorted deterministically
+    let mut values = values
+        .map(|(key, value)| {
+            let mut label = Vec::with_capacity(16);
+            key.write(&mut label).map(|_| (label, *key, *value))
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+
+    let folded = match limit {
+        LabelLimit::Unlimited => Vec::new(),
+        LabelLimit::Top(k) => {
+            values.sort_by(|(label, _, value), (other_label, _, other_value)| {
+                other_value.cmp(value).then_with(|| label.cmp(other_label))
+            });
+            let (exported, mut folded): (Vec<_>, Vec<_>) =
+                values.into_iter().partition(|(label, _, _)| selected.contains(label));
+            let rest = folded.split_off(k.saturating_sub(selected.len()).min(folded.len()));
+            for (label, _, _) in folded.iter() {
+                let _ = selected.insert(label.clone());
+            }
+            values = exported;
+            values.append(&mut folded);
+            values.sort_by(|(label, _, value), (other_label, _, other_value)| {
+                other_value.cmp(value).then_with(|| label.cmp(other_label))
+            });
+            rest
         }
+        LabelLimit::Allow(allowed) => {
+            let (exported, folded) = values.into_iter().partition(|(label, _, _)| {
+                allowed
+                    .iter()
+                    .any(|allowed| allowed.as_bytes().eq_ignore_ascii_case(label))
+            });
+            values = exported;
+            folded
+        }
+    };
 
-        Ok(w)
+    Ok(Folded {
+        values: values.into_iter().map(|(_, key, value)| (key, value)).collect(),
+        other: folded.iter().fold(0u64, |sum, (_, _, value)| sum.wrapping_add(*value)),
+        count: folded.len(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::{self, FromStr};
+    use std::time::Duration;
+
+    use crate::{
+        Descriptor, Filter, Format, LabelLimit, LabelSelection, Labels, Measurement, MeasurementOptions, Profile,
+        Statistics, ThreadSeries, Window,
+    };
+
+    static STATS: &str = include_str!("../../assets/test_text_stats.txt");
+
+    fn render(s: Statistics, previous: Option<&Statistics>, options: &MeasurementOptions) -> String {
+        let body = Measurement::observe_since(s, previous, options).unwrap().drain();
+        String::from_utf8(body).unwrap()
+    }
+
+    #[test]
+    fn test_quantiles() {
+        let stats = Statistics::from_str(STATS).unwrap();
+        let options = MeasurementOptions {
+            quantiles: vec![0.5, 0.99],
+            ..Default::default()
+        };
+
+        let body = render(stats.clone(), None, &options);
+        assert!(body.contains("# TYPE unbound_response_time_quantile_seconds gauge\n"));
+        let median = body
+            .lines()
+            .find_map(|line| line.strip_prefix("unbound_response_time_quantile_seconds{quantile=\"0.5\"} "))
+            .and_then(|value| value.parse::<f64>().ok());
+        approx::assert_relative_eq!(median.unwrap(), stats.histogram.quantile(0.5).unwrap());
+        assert!(body.contains("unbound_response_time_quantile_seconds{quantile=\"0.99\"} "));
+
+        let body = render(stats, None, &MeasurementOptions::default());
+        assert!(!body.contains("unbound_response_time_quantile_seconds"));
+    }
+
+    #[test]
+    fn test_quantiles_interval() {
+        let previous = Statistics::from_str(STATS).unwrap();
+        let options = MeasurementOptions {
+            quantiles: vec![0.5],
+            quantiles_window: Window::Interval,
+            ..Default::default()
+        };
+        assert!(options.needs_previous());
+
+        // Nothing to compare with yet
+        let body = render(previous.clone(), None, &options);
+        assert!(!body.contains("unbound_response_time_quantile_seconds{"));
+
+        let mut current = previous.clone();
+        current.time.now += Duration::from_secs(10);
+        current.total.num_queries += 2;
+        current.histogram = Default::default();
+        for (le, count) in previous.histogram.raw_buckets() {
+            let count = if le == Duration::from_secs(2) { count + 2 } else { count };
+            current.histogram.push(le, count);
+        }
+
+        // Both queries since the previous scrape took `1..2` seconds
+        let body = render(current, Some(&previous), &options);
+        assert!(body.contains("unbound_response_time_quantile_seconds{quantile=\"0.5\"} 1.5\n"));
+    }
+
+    #[test]
+    fn test_histogram_buckets() {
+        let stats = Statistics::from_str(STATS).unwrap();
+        let options = MeasurementOptions {
+            histogram_buckets: Some(vec![Duration::from_micros(131_072), Duration::from_secs(1)]),
+            ..Default::default()
+        };
+
+        let body = render(stats.clone(), None, &options);
+        let buckets = body
+            .lines()
+            .filter(|line| line.starts_with("unbound_response_time_seconds_bucket"))
+            .collect::<Vec<_>>();
+        assert_eq!(buckets.len(), 3);
+        assert!(buckets[0].starts_with("unbound_response_time_seconds_bucket{le=\"0.131072\"} "));
+        assert!(buckets[1].starts_with("unbound_response_time_seconds_bucket{le=\"1.0\"} "));
+        assert_eq!(
+            buckets[2],
+            format!(
+                "unbound_response_time_seconds_bucket{{le=\"+Inf\"}} {}",
+                stats.histogram.count()
+            )
+        );
+    }
+
+    #[test]
+    fn test_families_grouped() {
+        let stats = Statistics::from_str(STATS).unwrap();
+        let body = render(stats, None, &MeasurementOptions::default());
+
+        // Per-thread samples are observed in the thread order, but written grouped by family
+        let families = body
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .map(|line| line.split(&['{', ' '][..]).next().unwrap())
+            .fold(Vec::<&str>::new(), |mut families, name| {
+                if families.last() != Some(&name) {
+                    families.push(name);
+                }
+                families
+            });
+        let mut unique = families.clone();
+        unique.sort_unstable();
+        unique.dedup();
+        assert_eq!(families.len(), unique.len());
+    }
+
+    #[test]
+    fn test_openmetrics() {
+        let stats = Statistics::from_str(STATS).unwrap();
+        let options = MeasurementOptions {
+            format: Format::OpenMetrics,
+            ..Default::default()
+        };
+        let created = (stats.time.now - stats.time.up).as_secs_f64();
+
+        let body = render(stats, None, &options);
+        assert!(body.ends_with("\n# EOF\n"));
+        assert!(body.contains(&format!(
+            "# TYPE unbound_queries counter\n\
+             # HELP unbound_queries Total number of queries received\n\
+             unbound_queries_total{{thread=\"0\"}} 696\n\
+             unbound_queries_created{{thread=\"0\"}} {}\n",
+            created
+        )));
+        assert!(body.contains(
+            "# TYPE unbound_time_up_seconds counter\n\
+             # UNIT unbound_time_up_seconds seconds\n"
+        ));
+        assert!(body.contains("# UNIT unbound_response_time_seconds seconds\n"));
+        assert!(body.contains(&format!("unbound_response_time_seconds_created {}\n", created)));
+        assert!(body.contains("# UNIT unbound_memory_caches_bytes bytes\n"));
+
+        // Deprecated `answers_bogus` duplicate is the same family as `answers_bogus_total`
+        assert_eq!(body.matches("# TYPE unbound_answers_bogus counter\n").count(), 1);
+        assert_eq!(body.matches("unbound_answers_bogus_total ").count(), 1);
+    }
+
+    #[test]
+    fn test_protobuf() {
+        fn varint(buf: &[u8], pos: &mut usize) -> usize {
+            let mut value = 0;
+            let mut shift = 0;
+            loop {
+                let byte = buf[*pos];
+                *pos += 1;
+                value |= usize::from(byte & 0x7F) << shift;
+                shift += 7;
+                if byte < 0x80 {
+                    return value;
+                }
+            }
+        }
+
+        let stats = Statistics::from_str(STATS).unwrap();
+        let text = render(stats.clone(), None, &MeasurementOptions::default());
+        let options = MeasurementOptions {
+            format: Format::Protobuf,
+            ..Default::default()
+        };
+        let body = Measurement::observe_since(stats, None, &options).unwrap().drain();
+
+        // Walk over the length-delimited `MetricFamily` messages, which all are starting with the name field
+        let mut names = Vec::new();
+        let mut pos = 0;
+        while pos < body.len() {
+            let length = varint(&body, &mut pos);
+            let message = &body[pos..pos + length];
+            assert_eq!(message[0], 0x0A);
+            let mut name_pos = 1;
+            let name_length = varint(message, &mut name_pos);
+            names.push(
+                str::from_utf8(&message[name_pos..name_pos + name_length])
+                    .unwrap()
+                    .to_string(),
+            );
+            pos += length;
+        }
+        assert_eq!(pos, body.len());
+
+        assert_eq!(names.len(), text.matches("# TYPE ").count());
+        assert!(names.iter().any(|name| name == "unbound_queries_total"));
+        assert!(names.iter().any(|name| name == "unbound_response_time_seconds"));
+        assert!(names.iter().any(|name| name == "unbound_answers_bogus"));
+    }
+
+    #[test]
+    fn test_constant_labels() {
+        let stats = Statistics::from_str(STATS).unwrap();
+        let options = MeasurementOptions {
+            labels: Labels::new().with("site", "ams1").with("role", "edge"),
+            ..Default::default()
+        };
+
+        let body = render(stats, None, &options);
+        assert!(body.contains("unbound_num_threads{site=\"ams1\",role=\"edge\"} 2\n"));
+        assert!(body.contains("unbound_queries_total{site=\"ams1\",role=\"edge\",thread=\"0\"} 696\n"));
+        assert!(body.contains("unbound_response_time_seconds_bucket{site=\"ams1\",role=\"edge\",le=\"+Inf\"} "));
+        assert!(body.contains("unbound_response_time_seconds_count{site=\"ams1\",role=\"edge\"} "));
+    }
+
+    #[test]
+    fn test_escaping() {
+        let mut stats = Statistics::from_str(STATS).unwrap();
+        stats.unknown = vec![("zone.a\\b\"c".to_string(), "1".to_string())];
+        let options = MeasurementOptions {
+            passthrough: true,
+            labels: Labels::new().with("zone", "a\\b\"c\nd"),
+            ..Default::default()
+        };
+
+        let body = render(stats.clone(), None, &options);
+        assert!(
+            body.contains("# HELP unbound_raw_zone_a_b_c Raw value of the unbound `zone.a\\\\b\"c` statistics key\n")
+        );
+        assert!(body.contains("unbound_raw_zone_a_b_c{zone=\"a\\\\b\\\"c\\nd\"} 1.0\n"));
+
+        let options = MeasurementOptions {
+            format: Format::OpenMetrics,
+            ..options
+        };
+        let body = render(stats, None, &options);
+        assert!(
+            body.contains("# HELP unbound_raw_zone_a_b_c Raw value of the unbound `zone.a\\\\b\\\"c` statistics key\n")
+        );
+    }
+
+    #[test]
+    fn test_invalid_names() {
+        let mut w = Measurement::default();
+        assert!(w.gauge("foo-bar", "Foo").set(1).is_err());
+        assert!(w.gauge("foo", "Foo").set_with_label("0thread", 0, 1).is_err());
+        assert!(w.gauge("foo", "Foo").set_with_label("thread", 0, 1).is_ok());
+    }
+
+    #[test]
+    fn test_namespace() {
+        let stats = Statistics::from_str(STATS).unwrap();
+        let options = MeasurementOptions {
+            namespace: Some("dns_resolver_".to_string()),
+            ..Default::default()
+        };
+        let body = render(stats.clone(), None, &options);
+        assert!(body.contains("# TYPE dns_resolver_queries_total counter\n"));
+        assert!(body.contains("dns_resolver_queries_total{thread=\"0\"} 696\n"));
+        assert!(body.contains("dns_resolver_response_time_seconds_count "));
+        assert!(!body.contains("unbound_"));
+
+        let options = MeasurementOptions {
+            namespace: Some(String::new()),
+            ..Default::default()
+        };
+        let body = render(stats.clone(), None, &options);
+        assert!(body.contains("\nqueries_total{thread=\"0\"} 696\n"));
+
+        let options = MeasurementOptions {
+            namespace: Some("0dns_".to_string()),
+            ..Default::default()
+        };
+        assert!(Measurement::observe_with(stats, &options).is_err());
+    }
+
+    #[test]
+    fn test_filter() {
+        let stats = Statistics::from_str(STATS).unwrap();
+        let options = MeasurementOptions {
+            filter: Filter::new().exclude(vec!["query_types_total", "dnscrypt_*"]),
+            ..Default::default()
+        };
+        let body = render(stats.clone(), None, &options);
+        assert!(!body.contains("unbound_query_types_total"));
+        assert!(!body.contains("unbound_dnscrypt_"));
+        assert!(body.contains("# TYPE unbound_query_classes_total counter\n"));
+
+        let options = MeasurementOptions {
+            filter: Filter::new().include(vec!["queries_total", "response_time_seconds"]),
+            ..Default::default()
+        };
+        let body = render(stats.clone(), None, &options);
+        assert_eq!(body.matches("# TYPE ").count(), 2);
+
+        // Deprecated duplicate is written if the family it duplicates is excluded
+        let options = MeasurementOptions {
+            format: Format::OpenMetrics,
+            filter: Filter::new()
+                .include(vec!["answers_bogus*"])
+                .exclude(vec!["answers_bogus_total"]),
+            ..Default::default()
+        };
+        let body = render(stats, None, &options);
+        assert_eq!(body.matches("# TYPE unbound_answers_bogus counter\n").count(), 1);
+        assert_eq!(body.matches("# TYPE ").count(), 1);
+    }
+
+    #[test]
+    fn test_descriptors() {
+        let stats = Statistics::from_str(STATS).unwrap();
+        let options = MeasurementOptions {
+            quantiles: vec![0.5],
+            ratios: true,
+            ..Default::default()
+        };
+        let body = render(stats, None, &options);
+
+        let mut rendered = Vec::new();
+        for line in body.lines() {
+            if let Some(header) = line.strip_prefix("# HELP unbound_") {
+                let mut parts = header.splitn(2, ' ');
+                let descriptor = Descriptor::find(parts.next().unwrap()).unwrap();
+                assert_eq!(parts.next(), Some(descriptor.help));
+                rendered.push(descriptor.name);
+            } else if let Some(header) = line.strip_prefix("# TYPE unbound_") {
+                let mut parts = header.splitn(2, ' ');
+                let descriptor = Descriptor::find(parts.next().unwrap()).unwrap();
+                assert_eq!(parts.next(), Some(descriptor.kind.as_str()));
+            } else {
+                let name_end = line.find(&['{', ' '][..]).unwrap();
+                let name = line[..name_end].strip_prefix("unbound_").unwrap();
+                let descriptor = Descriptor::find(name)
+                    .or_else(|| Descriptor::find(name.trim_end_matches("_bucket")))
+                    .or_else(|| Descriptor::find(name.trim_end_matches("_sum")))
+                    .or_else(|| Descriptor::find(name.trim_end_matches("_count")))
+                    .unwrap_or_else(|| panic!("Sample '{}' is not described", line));
+
+                let labels = match line.find('{') {
+                    Some(start) => line[start + 1..line.rfind('}').unwrap()]
+                        .split("\",")
+                        .map(|pair| pair.split('=').next().unwrap())
+                        .filter(|label| *label != "le")
+                        .collect::<Vec<_>>(),
+                    None => Vec::new(),
+                };
+                assert_eq!(labels, descriptor.labels, "Labels mismatch in '{}'", line);
+            }
+        }
+
+        // There are no EDE codes and RPZ actions in the statistics and the last two families are written by the server
+        let expected = Descriptor::all()
+            .iter()
+            .map(|descriptor| descriptor.name)
+            .filter(|name| !["answer_ede_total", "rpz_action_total", "up", "scrape_duration_seconds"].contains(name))
+            .collect::<Vec<_>>();
+        assert_eq!(rendered, expected);
+    }
+
+    #[test]
+    fn test_observe_labeled() {
+        let stats = Statistics::from_str(STATS).unwrap();
+        let options = MeasurementOptions {
+            labels: Labels::new().with("site", "ams1"),
+            ..Default::default()
+        };
+        let mut m = Measurement::observe_with(vec![stats.clone(), stats.clone()].into_iter().sum(), &options).unwrap();
+        m.observe_labeled(
+            stats,
+            None,
+            &mut LabelSelection::new(),
+            &options,
+            &Labels::new().with("member", "10.0.0.1:8953"),
+        )
+        .unwrap();
+        let body = String::from_utf8(m.drain()).unwrap();
+
+        assert_eq!(body.matches("# TYPE unbound_num_threads gauge\n").count(), 1);
+        assert!(body.contains(
+            "unbound_num_threads{site=\"ams1\"} 4\n\
+             unbound_num_threads{site=\"ams1\",member=\"10.0.0.1:8953\"} 2\n"
+        ));
+        assert!(body.contains("unbound_queries_total{site=\"ams1\",member=\"10.0.0.1:8953\",thread=\"0\"} "));
+    }
+
+    #[test]
+    fn test_ratios() {
+        let stats = Statistics::from_str(STATS).unwrap();
+        let mut previous = stats.clone();
+        previous.total.num_cache_hits -= 100;
+        previous.total.num_cache_miss -= 100;
+        let options = MeasurementOptions {
+            ratios: true,
+            ..Default::default()
+        };
+        assert!(options.needs_previous());
+
+        let body = render(stats.clone(), None, &options);
+        assert!(body.contains("# TYPE unbound_cache_hit_ratio gauge\n"));
+        assert!(body.contains("unbound_cache_hit_ratio{window=\"lifetime\"} 0.16442"));
+        assert!(body.contains("unbound_answers_servfail_ratio{window=\"lifetime\"} 0.0\n"));
+        assert!(body.contains("unbound_answers_bogus_ratio{window=\"lifetime\"} 0.0\n"));
+        assert!(!body.contains("window=\"interval\""));
+
+        let body = render(stats, Some(&previous), &options);
+        assert!(body.contains("unbound_cache_hit_ratio{window=\"interval\"} 0.5\n"));
+        // No queries were answered in the interval
+        assert!(!body.contains("unbound_answers_servfail_ratio{window=\"interval\"}"));
+        assert!(body.contains("unbound_request_list_avg_to_max_ratio{window=\"lifetime\"} "));
+        assert!(!body.contains("unbound_request_list_avg_to_max_ratio{window=\"interval\"}"));
+        assert!(body.contains("unbound_answers_servfail_ratio{window=\"lifetime\"} 0.0\n"));
+    }
+
+    #[test]
+    fn test_profile() {
+        let stats = Statistics::from_str(STATS).unwrap();
+        let render_with = |profile| {
+            let options = MeasurementOptions {
+                format: Format::OpenMetrics,
+                profile,
+                ..Default::default()
+            };
+            render(stats.clone(), None, &options)
+        };
+
+        let body = render_with(Profile::Kumina);
+        assert!(body.contains("# TYPE unbound_answers_bogus counter\n"));
+        assert!(body.contains("# TYPE unbound_msg_cache_count gauge\n"));
+        assert!(!body.contains("unbound_cache_count_total"));
+        assert!(!body.contains("unbound_expired_responses_total"));
+        assert!(!body.contains("unbound_num_threads"));
+
+        let body = render_with(Profile::Letsencrypt);
+        assert!(body.contains("# TYPE unbound_zero_ttl_responses counter\n"));
+        assert!(body.contains("# TYPE unbound_query_https counter\n"));
+        assert!(!body.contains("unbound_num_threads"));
+        assert!(!body.contains("unbound_expired_responses_total"));
+
+        let body = render_with(Profile::Native);
+        assert!(body.contains("# TYPE unbound_answers_bogus counter\n"));
+        assert!(body.contains("unbound_answers_bogus_total 0\n"));
+        assert!(!body.contains("unbound_answers_bogus 0\n"));
+        assert!(body.contains("# TYPE unbound_expired_responses counter\n"));
+        assert!(!body.contains("unbound_zero_ttl_responses_total"));
+        assert!(!body.contains("unbound_msg_cache_count"));
+    }
+
+    #[test]
+    fn test_thread_series() {
+        let stats = Statistics::from_str(STATS).unwrap();
+        let render_with = |thread_series| {
+            let options = MeasurementOptions {
+                thread_series,
+                ..Default::default()
+            };
+            render(stats.clone(), None, &options)
+        };
+
+        let body = render_with(ThreadSeries::PerThread);
+        assert!(body.contains("unbound_queries_total{thread=\"0\"} 696\n"));
+        assert!(!body.contains("thread=\"total\""));
+
+        let body = render_with(ThreadSeries::PerThreadAndTotal);
+        assert!(body.contains(
+            "unbound_queries_total{thread=\"0\"} 696\n\
+             unbound_queries_total{thread=\"1\"} 642\n\
+             unbound_queries_total{thread=\"total\"} 1338\n"
+        ));
+
+        let body = render_with(ThreadSeries::Total);
+        assert!(body.contains(
+            "# HELP unbound_queries_total Total number of queries received\n\
+             unbound_queries_total{thread=\"total\"} 1338\n#"
+        ));
+
+        let body = render_with(ThreadSeries::Unlabeled);
+        assert!(body.contains("\nunbound_queries_total 1338\n"));
+        assert!(!body.contains("thread="));
+    }
+
+    #[test]
+    fn test_label_limits() {
+        let stats = Statistics::from_str(STATS).unwrap();
+        let options = MeasurementOptions {
+            query_types_limit: LabelLimit::Top(3),
+            query_classes_limit: LabelLimit::Allow(vec!["in".to_string()]),
+            query_opcodes_limit: LabelLimit::Top(1),
+            ..Default::default()
+        };
+        let body = render(stats, None, &options);
+
+        let types = body
+            .lines()
+            .filter(|line| line.starts_with("unbound_query_types_total{"))
+            .collect::<Vec<_>>();
+        assert_eq!(types.len(), 4);
+        assert!(types.contains(&"unbound_query_types_total{type=\"other\"} 13947703"));
+        assert!(types.contains(&"unbound_query_types_total{type=\"A\"} 4576639648"));
+        assert!(types.contains(&"unbound_query_types_total{type=\"AAAA\"} 151992709"));
+        assert!(types.contains(&"unbound_query_types_total{type=\"HTTPS\"} 140375490"));
+
+        assert!(body.contains("unbound_query_classes_total{class=\"other\"} 23320\n"));
+        assert!(body.contains("unbound_query_classes_total{class=\"IN\"} 4882932525\n"));
+        assert_eq!(body.matches("unbound_query_classes_total{").count(), 2);
+
+        assert!(body.contains("unbound_query_opcodes_total{opcode=\"other\"} 0\n"));
+        assert!(body.contains("unbound_query_opcodes_total{opcode=\"QUERY\"} 1338\n"));
+
+        assert!(body.contains("unbound_exporter_labels_folded{label=\"type\"} 20\n"));
+        assert!(body.contains("unbound_exporter_labels_folded{label=\"class\"} 5\n"));
+        assert!(body.contains("unbound_exporter_labels_folded{label=\"opcode\"} 0\n"));
+    }
+
+    #[test]
+    fn test_label_selection() {
+        let stats = Statistics::from_str(STATS).unwrap();
+        let options = MeasurementOptions {
+            query_types_limit: LabelLimit::Top(2),
+            ..Default::default()
+        };
+        let mut selection = LabelSelection::new();
+        let render_selected = |stats, selection: &mut LabelSelection| {
+            let body = Measurement::observe_selected(stats, None, selection, &options)
+                .unwrap()
+                .drain();
+            String::from_utf8(body).unwrap()
+        };
+
+        let body = render_selected(stats.clone(), &mut selection);
+        assert!(body.contains("unbound_query_types_total{type=\"A\"} 4576639648\n"));
+        assert!(body.contains("unbound_query_types_total{type=\"AAAA\"} 151992709\n"));
+        assert!(!body.contains("unbound_query_types_total{type=\"HTTPS\"}"));
+
+        // HTTPS is the most queried type now, but the selected types are kept
+        let mut next = stats;
+        for (rtype, value) in next.query_types.iter_mut() {
+            if rtype.to_string() == "HTTPS" {
+                *value += 10_000_000_000;
+            }
+        }
+        let body = render_selected(next, &mut selection);
+        assert!(body.contains("unbound_query_types_total{type=\"A\"} 4576639648\n"));
+        assert!(body.contains("unbound_query_types_total{type=\"AAAA\"} 151992709\n"));
+        assert!(!body.contains("unbound_query_types_total{type=\"HTTPS\"}"));
+        assert!(body.contains("unbound_exporter_labels_folded{label=\"type\"} 21\n"));
     }
 }
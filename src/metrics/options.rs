@@ -0,0 +1,175 @@
+use std::collections::HashSet;
+use std::time::Duration;
+
+use super::{Filter, Labels, Profile};
+
+/// Settings which are affecting the `Measurement::observe_with` output.
+#[derive(Debug, Clone, Default)]
+pub struct MeasurementOptions {
+    /// Export the statistics keys which are not known to the exporter (see `Statistics::unknown`)
+    /// with the `unbound_raw_` prefix.
+    pub passthrough: bool,
+    /// Response time quantiles (ex. `0.5` or `0.99`) to export as the `unbound_response_time_quantile_seconds`
+    /// gauges, estimated with the `Histogram::quantile`.
+    pub quantiles: Vec<f64>,
+    /// Observations which quantiles are estimated for.
+    pub quantiles_window: Window,
+    /// Custom upper bounds (sorted in ascending order) of the `unbound_response_time_seconds` histogram buckets
+    /// instead of the 40 `unbound` ones, see `Histogram::rebucket` for details.
+    pub histogram_buckets: Option<Vec<Duration>>,
+    /// Export the derived ratio gauges (ex. `unbound_cache_hit_ratio`) with the `window="lifetime"` label
+    /// and, if the previous observation is given, with the `window="interval"` label
+    /// for the time passed since it (see `Measurement::observe_since`).
+    pub ratios: bool,
+    /// Exposition format of the output.
+    pub format: Format,
+    /// Constant labels added to every series, ex. `site="ams1"`.
+    ///
+    /// Labels set by the exporter itself (ex. `thread`) are taking precedence over them.
+    pub labels: Labels,
+    /// Prefix of the metric names (ex. `dns_resolver_` or an empty one), `unbound_` if not set.
+    pub namespace: Option<String>,
+    /// Families to render, see `Filter` for details.
+    pub filter: Filter,
+    /// Families to render for compatibility with another exporter, see `Profile` for details.
+    pub profile: Profile,
+    /// Series of the per-thread families (ex. `queries_total`) to export.
+    pub thread_series: ThreadSeries,
+    /// Limit of the `type` label values of the `query_types_total` family.
+    pub query_types_limit: LabelLimit,
+    /// Limit of the `class` label values of the `query_classes_total` family.
+    pub query_classes_limit: LabelLimit,
+    /// Limit of the `opcode` label values of the `query_opcodes_total` family.
+    pub query_opcodes_limit: LabelLimit,
+}
+
+impl MeasurementOptions {
+    /// Returns `true` if the output depends on the previous observation,
+    /// see `Measurement::observe_since`.
+    pub fn needs_previous(&self) -> bool {
+        (!self.quantiles.is_empty() && self.quantiles_window == Window::Interval) || self.ratios
+    }
+}
+
+/// Time window the derived metrics are computed for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Window {
+    /// Since the `unbound` start or its statistics reset.
+    Lifetime,
+    /// Since the previous observation.
+    ///
+    /// Nothing is exported for the first observation, as there is no previous one yet.
+    Interval,
+}
+
+impl Default for Window {
+    fn default() -> Self {
+        Window::Lifetime
+    }
+}
+
+/// Limit of the distinct label values exported for the query types, classes or opcodes,
+/// which can be inflated by anyone sending the queries with random values.
+///
+/// Values over the limit are folded into the `other` label value
+/// and counted by the `exporter_labels_folded` gauge.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LabelLimit {
+    /// All values are exported.
+    Unlimited,
+    /// Values with the `k` highest counters are exported.
+    ///
+    /// Values once selected are kept in the `LabelSelection` and exported on the next scrapes too,
+    /// new ones are selected only until there are `k` of them.
+    Top(usize),
+    /// Values from the list are exported, they are compared with the exported label values
+    /// (ex. `AAAA`, `HTTPS` or `TYPE65534`) case-insensitively.
+    Allow(Vec<String>),
+}
+
+impl Default for LabelLimit {
+    fn default() -> Self {
+        LabelLimit::Unlimited
+    }
+}
+
+/// Label values selected by the `LabelLimit::Top` limits on the previous scrapes of the same source.
+///
+/// Selection is only extended, so the exported series are not disappearing
+/// and the `other` counters are not going down when the most queried values change.
+#[derive(Debug, Clone, Default)]
+pub struct LabelSelection {
+    pub(crate) types: HashSet<Vec<u8>>,
+    pub(crate) classes: HashSet<Vec<u8>>,
+    pub(crate) opcodes: HashSet<Vec<u8>>,
+}
+
+impl LabelSelection {
+    pub fn new() -> LabelSelection {
+        LabelSelection::default()
+    }
+}
+
+/// Series exported for the families which `unbound` reports per thread.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThreadSeries {
+    /// Series for each thread with the `thread="N"` label.
+    PerThread,
+    /// Series for each thread and the `thread="total"` series with the `Statistics::total` values.
+    PerThreadAndTotal,
+    /// Only the `thread="total"` series.
+    Total,
+    /// Only the `Statistics::total` values without the `thread` label.
+    Unlabeled,
+}
+
+impl ThreadSeries {
+    /// Returns `true` if the series for each thread are exported.
+    pub fn per_thread(self) -> bool {
+        match self {
+            ThreadSeries::PerThread | ThreadSeries::PerThreadAndTotal => true,
+            ThreadSeries::Total | ThreadSeries::Unlabeled => false,
+        }
+    }
+}
+
+impl Default for ThreadSeries {
+    fn default() -> Self {
+        ThreadSeries::PerThread
+    }
+}
+
+/// Metrics exposition format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Prometheus text format, version 0.0.4.
+    Prometheus,
+    /// OpenMetrics text format, version 1.0.0.
+    ///
+    /// Comparing to the Prometheus format, it has `# UNIT` metadata, counter families
+    /// are named without the `_total` suffix and followed by the `_created` samples
+    /// (derived from the `unbound` uptime), and output is terminated with `# EOF` line.
+    OpenMetrics,
+    /// Prometheus protobuf format: stream of the length-delimited `io.prometheus.client.MetricFamily` messages.
+    ///
+    /// Metrics are named same as in the Prometheus text format; counters and histogram
+    /// are having the created timestamps, same as in the OpenMetrics format.
+    Protobuf,
+}
+
+impl Format {
+    /// `Content-Type` header value for the format.
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Format::Prometheus => "text/plain; version=0.0.4; charset=utf-8",
+            Format::OpenMetrics => "application/openmetrics-text; version=1.0.0; charset=utf-8",
+            Format::Protobuf => super::protobuf::CONTENT_TYPE,
+        }
+    }
+}
+
+impl Default for Format {
+    fn default() -> Self {
+        Format::Prometheus
+    }
+}
@@ -0,0 +1,225 @@
+//! Generic export of the statistics keys which are not known to the exporter.
+//!
+//! Key is converted into the metric name by replacing all characters not allowed
+//! by Prometheus with the underscore (ex. `num.query.foo-bar` becomes `unbound_raw_num_query_foo_bar`)
+//! and `threadN.` or `total.` prefix is converted into the `thread="N"` or `thread="total"` label.
+//! Same as for the known per-thread metrics, these series are selected by the `ThreadSeries`.
+//!
+//! Different keys might be converted into the same name (ex. `num.foo-bar` and `num.foo.bar`),
+//! in which case only the first key is exported and the other ones are logged and ignored,
+//! as the duplicated series are failing the whole scrape.
+//!
+//! Since there is no information about the value semantics, metric type is guessed:
+//! `num.*` keys are exported as counters and everything else as gauges.
+
+use std::collections::BTreeMap;
+use std::io;
+
+use super::{Measurement, ThreadSeries};
+
+impl Measurement {
+    #[allow(unused_results)]
+    pub(crate) fn passthrough(&mut self, unknown: &[(String, String)], thread_series: ThreadSeries) -> io::Result<()> {
+        // Grouping samples by the metric name, so each metric will have only one header
+        let mut metrics = BTreeMap::<String, (&str, Vec<(Option<&str>, f64)>)>::new();
+
+        for (key, raw_value) in unknown {
+            let value = match raw_value.parse::<f64>() {
+                Ok(value) => value,
+                Err(..) => {
+                    log::debug!("Unable to export '{}' key, value '{}' is not a number", key, raw_value);
+                    continue;
+                }
+            };
+            let (thread, key) = split_thread(key);
+            let thread = match select_thread(thread, thread_series) {
+                Some(thread) => thread,
+                None => continue,
+            };
+
+            let (known, samples) = metrics.entry(key_to_name(key)).or_insert_with(|| (key, Vec::new()));
+            if *known != key || samples.iter().any(|(known_thread, _)| *known_thread == thread) {
+                log::warn!(
+                    "Unable to export '{}' key, it has the same metric name as the '{}' key",
+                    key,
+                    known
+                );
+                continue;
+            }
+            samples.push((thread, value));
+        }
+
+        for (name, (key, samples)) in metrics {
+            let help = format!("Raw value of the unbound `{}` statistics key", key);
+            let mut metric = if name.starts_with("raw_num_") {
+                self.counter(name, help)
+            } else {
+                self.gauge(name, help)
+            };
+
+            for (thread, value) in samples {
+                match thread {
+                    Some(thread) => metric.set_with_label("thread", thread, value)?,
+                    None => metric.set(value)?,
+                };
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Split the `threadN.` or `total.` prefix from the key, if any.
+fn split_thread(key: &str) -> (Option<&str>, &str) {
+    let mut parts = key.splitn(2, '.');
+    match (parts.next(), parts.next()) {
+        (Some("total"), Some(rest)) => (Some("total"), rest),
+        (Some(prefix), Some(rest)) if prefix.starts_with("thread") => {
+            let id = &prefix["thread".len()..];
+            if !id.is_empty() && id.bytes().all(|b| b.is_ascii_digit()) {
+                (Some(id), rest)
+            } else {
+                (None, key)
+            }
+        }
+        _ => (None, key),
+    }
+}
+
+/// Label of the `thread` series, which is exported according to the `thread_series`;
+/// `None` if the series should not be exported at all.
+fn select_thread(thread: Option<&str>, thread_series: ThreadSeries) -> Option<Option<&str>> {
+    match (thread, thread_series) {
+        (None, _) => Some(None),
+        (Some("total"), ThreadSeries::PerThreadAndTotal) | (Some("total"), ThreadSeries::Total) => Some(thread),
+        (Some("total"), ThreadSeries::Unlabeled) => Some(None),
+        (Some("total"), ThreadSeries::PerThread) => None,
+        (Some(_), thread_series) if thread_series.per_thread() => Some(thread),
+        (Some(_), _) => None,
+    }
+}
+
+/// Convert the `unbound` statistics key into the valid Prometheus metric name (without `unbound_` prefix).
+fn key_to_name(key: &str) -> String {
+    let mut name = String::with_capacity("raw_".len() + key.len());
+    name.push_str("raw_");
+    name.extend(key.chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }));
+
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{key_to_name, select_thread, split_thread};
+    use crate::{Measurement, MeasurementOptions, Statistics, ThreadSeries};
+
+    #[test]
+    fn test_key_to_name() {
+        assert_eq!(key_to_name("num.query.foo-bar"), "raw_num_query_foo_bar");
+        assert_eq!(key_to_name("mem.mod.new_module"), "raw_mem_mod_new_module");
+    }
+
+    #[test]
+    fn test_split_thread() {
+        assert_eq!(split_thread("thread12.num.foo"), (Some("12"), "num.foo"));
+        assert_eq!(split_thread("total.num.foo"), (Some("total"), "num.foo"));
+        assert_eq!(split_thread("threads.num.foo"), (None, "threads.num.foo"));
+        assert_eq!(split_thread("num.foo"), (None, "num.foo"));
+    }
+
+    #[test]
+    fn test_name_collision() {
+        let statistics = Statistics {
+            unknown: vec![
+                ("num.foo-bar".to_string(), "1".to_string()),
+                ("num.foo.bar".to_string(), "2".to_string()),
+                ("num.baz".to_string(), "3".to_string()),
+                ("total.num.baz".to_string(), "4".to_string()),
+            ],
+            ..Default::default()
+        };
+        let options = MeasurementOptions {
+            passthrough: true,
+            thread_series: ThreadSeries::Unlabeled,
+            ..Default::default()
+        };
+
+        let body = Measurement::observe_with(statistics, &options).unwrap().drain();
+        let body = String::from_utf8(body).unwrap();
+
+        assert!(body.contains("unbound_raw_num_foo_bar 1.0\n"));
+        assert_eq!(body.matches("\nunbound_raw_num_foo_bar ").count(), 1);
+        assert!(body.contains("unbound_raw_num_baz 3.0\n"));
+        assert_eq!(body.matches("\nunbound_raw_num_baz ").count(), 1);
+    }
+
+    #[test]
+    fn test_select_thread() {
+        assert_eq!(select_thread(None, ThreadSeries::Total), Some(None));
+        assert_eq!(select_thread(Some("0"), ThreadSeries::PerThread), Some(Some("0")));
+        assert_eq!(select_thread(Some("total"), ThreadSeries::PerThread), None);
+        assert_eq!(
+            select_thread(Some("0"), ThreadSeries::PerThreadAndTotal),
+            Some(Some("0"))
+        );
+        assert_eq!(
+            select_thread(Some("total"), ThreadSeries::PerThreadAndTotal),
+            Some(Some("total"))
+        );
+        assert_eq!(select_thread(Some("0"), ThreadSeries::Total), None);
+        assert_eq!(select_thread(Some("total"), ThreadSeries::Total), Some(Some("total")));
+        assert_eq!(select_thread(Some("0"), ThreadSeries::Unlabeled), None);
+        assert_eq!(select_thread(Some("total"), ThreadSeries::Unlabeled), Some(None));
+    }
+
+    #[test]
+    fn test_passthrough() {
+        let statistics = Statistics {
+            unknown: vec![
+                ("thread0.num.foo".to_string(), "1".to_string()),
+                ("thread1.num.foo".to_string(), "2".to_string()),
+                ("total.num.foo".to_string(), "3".to_string()),
+                ("mem.bar".to_string(), "3.5".to_string()),
+                ("num.baz".to_string(), "not a number".to_string()),
+                ("mem.nan".to_string(), "nan".to_string()),
+                ("mem.inf".to_string(), "-inf".to_string()),
+            ],
+            ..Default::default()
+        };
+        let options = MeasurementOptions {
+            passthrough: true,
+            thread_series: ThreadSeries::PerThreadAndTotal,
+            ..Default::default()
+        };
+
+        let body = Measurement::observe_with(statistics.clone(), &options).unwrap().drain();
+        let body = String::from_utf8(body).unwrap();
+
+        assert!(body.contains(
+            "# TYPE unbound_raw_num_foo counter\n\
+             # HELP unbound_raw_num_foo Raw value of the unbound `num.foo` statistics key\n\
+             unbound_raw_num_foo{thread=\"0\"} 1.0\n\
+             unbound_raw_num_foo{thread=\"1\"} 2.0\n\
+             unbound_raw_num_foo{thread=\"total\"} 3.0\n"
+        ));
+        assert!(body.contains("# TYPE unbound_raw_mem_bar gauge\n"));
+        assert!(body.contains("unbound_raw_mem_bar 3.5\n"));
+        assert!(!body.contains("unbound_raw_num_baz"));
+        assert!(body.contains("unbound_raw_mem_nan NaN\n"));
+        assert!(body.contains("unbound_raw_mem_inf -Inf\n"));
+
+        // Totals are not exported along with the per-thread series by default
+        let body = Measurement::observe_with(
+            statistics,
+            &MeasurementOptions {
+                passthrough: true,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+        .drain();
+        let body = String::from_utf8(body).unwrap();
+        assert!(body.contains("unbound_raw_num_foo{thread=\"1\"} 2.0\n"));
+        assert!(!body.contains("unbound_raw_num_foo{thread=\"total\"}"));
+    }
+}
@@ -0,0 +1,138 @@
+use super::descriptor::{Descriptor, Origin};
+
+/// Set of the metric families to export for compatibility with the dashboards and alerts
+/// built for another `unbound` exporter.
+///
+/// Family names are the same in every profile, profiles are differing only in the families exported.
+/// Opt-in families (`raw_*` passthrough keys, response time quantiles, ratios)
+/// and the exporter own ones are exported in every profile, see `Origin`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Profile {
+    /// All families, including the deprecated duplicates.
+    All,
+    /// Only the families exported by the kumina `unbound_exporter`.
+    Kumina,
+    /// Only the families exported by the letsencrypt `unbound_exporter`, which is a successor
+    /// of the kumina one extended with some of the newer `unbound` statistics.
+    Letsencrypt,
+    /// All families except the deprecated duplicates of the other ones.
+    Native,
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Profile::All
+    }
+}
+
+const KUMINA: &[&str] = &[
+    "answer_rcodes_total",
+    "answers_bogus",
+    "answers_secure_total",
+    "cache_hits_total",
+    "cache_misses_total",
+    "memory_caches_bytes",
+    "memory_modules_bytes",
+    "msg_cache_count",
+    "prefetches_total",
+    "queries_total",
+    "query_classes_total",
+    "query_edns_DO_total",
+    "query_edns_present_total",
+    "query_flags_total",
+    "query_ipv6_total",
+    "query_opcodes_total",
+    "query_tcp_total",
+    "query_tls_total",
+    "query_types_total",
+    "recursion_time_seconds_avg",
+    "recursion_time_seconds_median",
+    "request_list_current_all",
+    "request_list_current_user",
+    "request_list_exceeded_total",
+    "request_list_overwritten_total",
+    "response_time_seconds",
+    "rrset_bogus_total",
+    "rrset_cache_count",
+    "time_elapsed_seconds",
+    "time_now_seconds",
+    "time_up_seconds_total",
+    "unwanted_queries_total",
+    "unwanted_replies_total",
+    "up",
+    "zero_ttl_responses_total",
+];
+
+/// Families added by the letsencrypt exporter to the `KUMINA` ones.
+const LETSENCRYPT: &[&str] = &[
+    "queries_ip_ratelimited_total",
+    "query_https_total",
+    "query_tls_resume_total",
+];
+
+/// Deprecated duplicates of the `answers_bogus_total`, `cache_count_total` and `expired_responses_total` families,
+/// which are not known to the upstream exporters.
+const DEPRECATED: &[&str] = &[
+    "answers_bogus",
+    "msg_cache_count",
+    "rrset_cache_count",
+    "zero_ttl_responses_total",
+];
+
+impl Profile {
+    /// Returns `true` if the `family` (name without namespace, ex. `query_types_total`) is exported.
+    pub fn exports(self, family: &str) -> bool {
+        // Passthrough keys are not described, as their names are coming from `unbound`
+        let common = family.starts_with("raw_")
+            || Descriptor::find(family).map_or(false, |descriptor| descriptor.origin != Origin::Statistics);
+
+        common
+            || match self {
+                Profile::All => true,
+                Profile::Kumina => KUMINA.contains(&family),
+                Profile::Letsencrypt => KUMINA.contains(&family) || LETSENCRYPT.contains(&family),
+                Profile::Native => !DEPRECATED.contains(&family),
+            }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Descriptor, Profile, DEPRECATED, KUMINA, LETSENCRYPT};
+
+    #[test]
+    fn test_exports() {
+        for profile in [Profile::All, Profile::Kumina, Profile::Letsencrypt, Profile::Native].iter() {
+            assert!(profile.exports("queries_total"));
+            assert!(profile.exports("raw_num_query_foo"));
+            assert!(profile.exports("exporter_unknown_keys"));
+            assert!(profile.exports("response_time_quantile_seconds"));
+        }
+
+        assert!(Profile::All.exports("answers_bogus"));
+        assert!(Profile::All.exports("answers_bogus_total"));
+
+        assert!(Profile::Kumina.exports("answers_bogus"));
+        assert!(!Profile::Kumina.exports("answers_bogus_total"));
+        assert!(!Profile::Kumina.exports("answer_ede_total"));
+        assert!(!Profile::Kumina.exports("exporter"));
+
+        assert!(Profile::Letsencrypt.exports("zero_ttl_responses_total"));
+        assert!(Profile::Letsencrypt.exports("query_https_total"));
+        assert!(!Profile::Letsencrypt.exports("expired_responses_total"));
+        assert!(!Profile::Letsencrypt.exports("num_threads"));
+        assert!(!Profile::Letsencrypt.exports("module_queries_total"));
+
+        assert!(!Profile::Native.exports("msg_cache_count"));
+        assert!(!Profile::Native.exports("zero_ttl_responses_total"));
+        assert!(Profile::Native.exports("cache_count_total"));
+        assert!(Profile::Native.exports("expired_responses_total"));
+    }
+
+    #[test]
+    fn test_families_described() {
+        for family in KUMINA.iter().chain(LETSENCRYPT).chain(DEPRECATED) {
+            assert!(Descriptor::find(family).is_some(), "Family {} is not described", family);
+        }
+    }
+}
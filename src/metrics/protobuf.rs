@@ -0,0 +1,216 @@
+//! Prometheus protobuf exposition format encoding.
+//!
+//! Only the small subset of the `io.prometheus.client` messages (see `metrics.proto`
+//! from the `prometheus/client_model` repository) is needed in here,
+//! so they are encoded by hand instead of bringing the protobuf code generation in.
+//!
+//! Samples of each family are encoded as `Metric` messages into the family buffer
+//! (already wrapped as the repeated `MetricFamily.metric` field entries)
+//! and the `MetricFamily` message itself is assembled when output is drained.
+
+use std::time::Duration;
+
+/// `Content-Type` of the length-delimited `MetricFamily` messages stream.
+pub const CONTENT_TYPE: &str = "application/vnd.google.protobuf; proto=io.prometheus.client.MetricFamily; \
+                                encoding=delimited";
+
+const VARINT: u8 = 0;
+const FIXED64: u8 = 1;
+const LENGTH_DELIMITED: u8 = 2;
+
+// `MetricFamily` fields
+const FAMILY_NAME: u32 = 1;
+const FAMILY_HELP: u32 = 2;
+const FAMILY_TYPE: u32 = 3;
+const FAMILY_METRIC: u32 = 4;
+
+// `Metric` fields
+const METRIC_LABEL: u32 = 1;
+const METRIC_GAUGE: u32 = 2;
+const METRIC_COUNTER: u32 = 3;
+const METRIC_HISTOGRAM: u32 = 7;
+
+// `LabelPair` fields
+const LABEL_NAME: u32 = 1;
+const LABEL_VALUE: u32 = 2;
+
+// `Gauge` and `Counter` fields
+const VALUE: u32 = 1;
+const COUNTER_CREATED: u32 = 3;
+
+// `Histogram` fields
+const HISTOGRAM_COUNT: u32 = 1;
+const HISTOGRAM_SUM: u32 = 2;
+const HISTOGRAM_BUCKET: u32 = 3;
+const HISTOGRAM_CREATED: u32 = 15;
+
+// `Bucket` fields
+const BUCKET_COUNT: u32 = 1;
+const BUCKET_UPPER_BOUND: u32 = 2;
+
+// `Timestamp` fields
+const TIMESTAMP_SECONDS: u32 = 1;
+const TIMESTAMP_NANOS: u32 = 2;
+
+/// `MetricType` enum value by the metric kind, as it is named in the text format.
+fn metric_type(kind: &str) -> u64 {
+    match kind {
+        "counter" => 0,
+        "gauge" => 1,
+        "summary" => 2,
+        "histogram" => 4,
+        _ => 3, // untyped
+    }
+}
+
+fn varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn key(buf: &mut Vec<u8>, field: u32, wire_type: u8) {
+    varint(buf, u64::from(field) << 3 | u64::from(wire_type));
+}
+
+fn uint64_field(buf: &mut Vec<u8>, field: u32, value: u64) {
+    key(buf, field, VARINT);
+    varint(buf, value);
+}
+
+fn double_field(buf: &mut Vec<u8>, field: u32, value: f64) {
+    key(buf, field, FIXED64);
+    buf.extend_from_slice(&value.to_bits().to_le_bytes());
+}
+
+fn bytes_field(buf: &mut Vec<u8>, field: u32, value: &[u8]) {
+    key(buf, field, LENGTH_DELIMITED);
+    varint(buf, value.len() as u64);
+    buf.extend_from_slice(value);
+}
+
+fn timestamp_field(buf: &mut Vec<u8>, field: u32, value: Duration) {
+    let mut timestamp = Vec::with_capacity(16);
+    uint64_field(&mut timestamp, TIMESTAMP_SECONDS, value.as_secs());
+    uint64_field(&mut timestamp, TIMESTAMP_NANOS, u64::from(value.subsec_nanos()));
+    bytes_field(buf, field, &timestamp);
+}
+
+/// Encode the `MetricFamily.metric` entry for the counter or gauge sample.
+pub fn sample(buf: &mut Vec<u8>, kind: &str, labels: &[(&str, &[u8])], value: f64, created: Option<Duration>) {
+    let mut metric = Vec::with_capacity(64);
+    label_fields(&mut metric, labels);
+
+    let mut inner = Vec::with_capacity(24);
+    double_field(&mut inner, VALUE, value);
+    match kind {
+        "counter" => {
+            if let Some(created) = created {
+                timestamp_field(&mut inner, COUNTER_CREATED, created);
+            }
+            bytes_field(&mut metric, METRIC_COUNTER, &inner);
+        }
+        _ => bytes_field(&mut metric, METRIC_GAUGE, &inner),
+    }
+
+    bytes_field(buf, FAMILY_METRIC, &metric);
+}
+
+fn label_fields(metric: &mut Vec<u8>, labels: &[(&str, &[u8])]) {
+    for (name, value) in labels {
+        let mut pair = Vec::with_capacity(name.len() + value.len() + 4);
+        bytes_field(&mut pair, LABEL_NAME, name.as_bytes());
+        bytes_field(&mut pair, LABEL_VALUE, value);
+        bytes_field(metric, METRIC_LABEL, &pair);
+    }
+}
+
+/// Append the bucket to the `Histogram` message fields.
+///
+/// `+Inf` bucket is implied by the sample count in this format, so it should be skipped.
+pub fn histogram_bucket(histogram: &mut Vec<u8>, upper_bound: f64, cumulative_count: u64) {
+    let mut bucket = Vec::with_capacity(20);
+    uint64_field(&mut bucket, BUCKET_COUNT, cumulative_count);
+    double_field(&mut bucket, BUCKET_UPPER_BOUND, upper_bound);
+    bytes_field(histogram, HISTOGRAM_BUCKET, &bucket);
+}
+
+pub fn histogram_sum(histogram: &mut Vec<u8>, sum: f64) {
+    double_field(histogram, HISTOGRAM_SUM, sum);
+}
+
+pub fn histogram_count(histogram: &mut Vec<u8>, count: u64) {
+    uint64_field(histogram, HISTOGRAM_COUNT, count);
+}
+
+/// Encode the `MetricFamily.metric` entry for the histogram from its already encoded fields.
+pub fn histogram(buf: &mut Vec<u8>, mut histogram: Vec<u8>, labels: &[(&str, &[u8])], created: Option<Duration>) {
+    if let Some(created) = created {
+        timestamp_field(&mut histogram, HISTOGRAM_CREATED, created);
+    }
+
+    let mut metric = Vec::with_capacity(histogram.len() + 64);
+    label_fields(&mut metric, labels);
+    bytes_field(&mut metric, METRIC_HISTOGRAM, &histogram);
+    bytes_field(buf, FAMILY_METRIC, &metric);
+}
+
+/// Write the length-delimited `MetricFamily` message with the `metrics` encoded by functions above.
+pub fn family(output: &mut Vec<u8>, name: &str, help: &str, kind: &str, metrics: &[u8]) {
+    let mut family = Vec::with_capacity(name.len() + help.len() + metrics.len() + 16);
+    bytes_field(&mut family, FAMILY_NAME, name.as_bytes());
+    bytes_field(&mut family, FAMILY_HELP, help.as_bytes());
+    uint64_field(&mut family, FAMILY_TYPE, metric_type(kind));
+    family.extend_from_slice(metrics);
+
+    varint(output, family.len() as u64);
+    output.extend_from_slice(&family);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_varint() {
+        let mut buf = Vec::new();
+        varint(&mut buf, 1);
+        varint(&mut buf, 300);
+        varint(&mut buf, u64::MAX);
+        assert_eq!(
+            buf,
+            vec![0x01, 0xAC, 0x02, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01]
+        );
+    }
+
+    #[test]
+    fn test_family() {
+        let mut metrics = Vec::new();
+        sample(&mut metrics, "gauge", &[("thread", b"0")], 1.5, None);
+
+        let mut output = Vec::new();
+        family(&mut output, "unbound_foo", "Foo", "gauge", &metrics);
+
+        // Length prefix and name
+        let mut expected = vec![46, 0x0A, 11];
+        expected.extend_from_slice(b"unbound_foo");
+        // help
+        expected.extend_from_slice(&[0x12, 3]);
+        expected.extend_from_slice(b"Foo");
+        // type: GAUGE
+        expected.extend_from_slice(&[0x18, 1]);
+        // metric
+        expected.extend_from_slice(&[0x22, 24]);
+        // metric.label
+        expected.extend_from_slice(&[0x0A, 11, 0x0A, 6]);
+        expected.extend_from_slice(b"thread");
+        expected.extend_from_slice(&[0x12, 1, b'0']);
+        // metric.gauge.value
+        expected.extend_from_slice(&[0x12, 9, 0x09]);
+        expected.extend_from_slice(&1.5f64.to_bits().to_le_bytes());
+
+        assert_eq!(output, expected);
+    }
+}
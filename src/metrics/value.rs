@@ -4,12 +4,115 @@ use std::time;
 
 use domain::base::iana::{Class, Opcode, Rcode, Rtype};
 
+use super::Format;
+use crate::statistics::{iana, RpzAction};
+
 pub trait MetricValue {
     fn write<T>(self, w: T) -> io::Result<()>
     where
         T: io::Write;
 }
 
+/// Writer escaping the backslash, newline and, optionally, double quote characters,
+/// as text formats are requiring for the label values and help text.
+pub struct Escaped<W> {
+    inner: W,
+    quotes: bool,
+}
+
+impl<W> Escaped<W>
+where
+    W: io::Write,
+{
+    pub fn label_value(inner: W) -> Self {
+        Self { inner, quotes: true }
+    }
+
+    /// Double quotes are escaped in the OpenMetrics help text only.
+    pub fn help(inner: W, format: Format) -> Self {
+        Self {
+            inner,
+            quotes: format == Format::OpenMetrics,
+        }
+    }
+}
+
+impl<W> io::Write for Escaped<W>
+where
+    W: io::Write,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut start = 0;
+        for (idx, byte) in buf.iter().enumerate() {
+            let escaped: &[u8] = match byte {
+                b'\\' => b"\\\\",
+                b'\n' => b"\\n",
+                b'"' if self.quotes => b"\\\"",
+                _ => continue,
+            };
+            self.inner.write_all(&buf[start..idx])?;
+            self.inner.write_all(escaped)?;
+            start = idx + 1;
+        }
+        self.inner.write_all(&buf[start..])?;
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Sample values, which should be available as numbers for the binary formats.
+pub trait NumericValue: MetricValue {
+    fn to_f64(self) -> f64;
+}
+
+macro_rules! numeric_value {
+    ($($ty:ty),*) => {
+        $(
+            impl NumericValue for $ty {
+                fn to_f64(self) -> f64 {
+                    self as f64
+                }
+            }
+        )*
+    };
+}
+
+numeric_value!(u16, u64, i32, usize);
+
+impl NumericValue for f64 {
+    fn to_f64(self) -> f64 {
+        self
+    }
+}
+
+impl NumericValue for time::Duration {
+    fn to_f64(self) -> f64 {
+        self.as_secs_f64()
+    }
+}
+
+impl<V> NumericValue for &V
+where
+    V: NumericValue + Copy,
+{
+    fn to_f64(self) -> f64 {
+        (*self).to_f64()
+    }
+}
+
+impl MetricValue for u16 {
+    fn write<T>(self, w: T) -> io::Result<()>
+    where
+        T: io::Write,
+    {
+        itoa::write(w, self).map(|_| ())
+    }
+}
+
 impl MetricValue for u64 {
     fn write<T>(self, w: T) -> io::Result<()>
     where
@@ -37,11 +140,18 @@ impl MetricValue for usize {
 }
 
 impl MetricValue for f64 {
-    fn write<T>(self, w: T) -> io::Result<()>
+    fn write<T>(self, mut w: T) -> io::Result<()>
     where
         T: io::Write,
     {
-        dtoa::write(w, self).map(|_| ())
+        // `dtoa` is not handling the non-finite values
+        if self.is_nan() {
+            w.write_all(b"NaN")
+        } else if self.is_infinite() {
+            w.write_all(if self > 0.0 { b"+Inf" } else { b"-Inf" })
+        } else {
+            dtoa::write(w, self).map(|_| ())
+        }
     }
 }
 
@@ -50,7 +160,7 @@ impl MetricValue for time::Duration {
     where
         T: io::Write,
     {
-        dtoa::write(w, self.as_secs_f64()).map(|_| ())
+        self.as_secs_f64().write(w)
     }
 }
 
@@ -68,16 +178,7 @@ impl MetricValue for Class {
     where
         T: io::Write,
     {
-        let res = match self {
-            Class::Any => w.write(b"ANY"),
-            other => {
-                let str = format!("{}", other);
-
-                w.write(str.as_bytes())
-            }
-        };
-
-        res.map(|_| ())
+        w.write_all(iana::class_to_str(self).as_bytes())
     }
 }
 
@@ -86,9 +187,7 @@ impl MetricValue for Opcode {
     where
         T: io::Write,
     {
-        let str = format!("{}", self);
-
-        w.write(str.as_bytes()).map(|_| ())
+        w.write_all(iana::opcode_to_str(self).as_bytes())
     }
 }
 
@@ -108,9 +207,16 @@ impl MetricValue for Rtype {
     where
         T: io::Write,
     {
-        let str = format!("{}", self);
+        w.write_all(iana::rtype_to_str(self).as_bytes())
+    }
+}
 
-        w.write(str.as_bytes()).map(|_| ())
+impl MetricValue for RpzAction {
+    fn write<T>(self, mut w: T) -> io::Result<()>
+    where
+        T: io::Write,
+    {
+        w.write_all(self.as_str().as_bytes())
     }
 }
 
@@ -125,3 +231,22 @@ where
         (*self).write(w)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::MetricValue;
+
+    fn render<V: MetricValue>(value: V) -> String {
+        let mut buf = Vec::new();
+        value.write(&mut buf).unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[test]
+    fn test_f64() {
+        assert_eq!(render(1.5f64), "1.5");
+        assert_eq!(render(f64::NAN), "NaN");
+        assert_eq!(render(f64::INFINITY), "+Inf");
+        assert_eq!(render(f64::NEG_INFINITY), "-Inf");
+    }
+}
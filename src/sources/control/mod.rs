@@ -1,9 +1,11 @@
+use std::collections::HashSet;
 use std::io;
 use std::marker::Unpin;
-use std::str::FromStr;
+use std::sync::Mutex;
 
 use super::Source;
-use crate::Statistics;
+use crate::statistics::{ParseMode, Parser, Statistics};
+use tokio::io::BufReader;
 use tokio::prelude::{AsyncRead, AsyncWrite, *};
 
 mod text;
@@ -25,11 +27,46 @@ pub trait RemoteControlTransport: Sized + Send + Sync {
 
 pub struct RemoteControlSource<T> {
     transport: T,
+    mode: ParseMode,
+    max_size: Option<usize>,
+    // Unknown keys which were already reported to the log,
+    // so the same key would not be logged on every scrape.
+    reported: Mutex<HashSet<String>>,
 }
 
 impl<T> RemoteControlSource<T> {
     pub fn new(transport: T) -> Self {
-        Self { transport }
+        Self::with_mode(transport, ParseMode::default())
+    }
+
+    pub fn with_mode(transport: T, mode: ParseMode) -> Self {
+        Self {
+            transport,
+            mode,
+            max_size: None,
+            reported: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Reject `unbound` responses larger than `bytes`.
+    pub fn max_size(mut self, bytes: usize) -> Self {
+        self.max_size = Some(bytes);
+        self
+    }
+
+    fn report(&self, statistics: &Statistics) {
+        let mut reported = self.reported.lock().unwrap_or_else(|e| e.into_inner());
+        for (key, _) in statistics.unknown.iter() {
+            if !reported.contains(key) {
+                log::warn!("Unable to parse '{}', unknown key", key);
+                let _ = reported.insert(key.clone());
+            }
+        }
+
+        // Malformed lines are not expected to repeat, so they are reported every time
+        for e in statistics.diagnostics.iter() {
+            log::warn!("Skipped malformed statistics line: {}", e);
+        }
     }
 }
 
@@ -48,10 +85,14 @@ where
         let mut socket = self.transport.connect().await?;
 
         socket.write_all(b"UBCT1 stats_noreset\n").await?;
-        let mut buffer = String::new();
 
-        let _ = socket.read_to_string(&mut buffer).await?;
+        let mut parser = Parser::with_mode(self.mode);
+        if let Some(bytes) = self.max_size {
+            parser = parser.max_size(bytes);
+        }
+        let statistics = parser.parse_reader(BufReader::new(socket)).await?;
+        self.report(&statistics);
 
-        Statistics::from_str(&buffer).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        Ok(statistics)
     }
 }
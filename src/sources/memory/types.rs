@@ -2,73 +2,40 @@
 
 // All functions in this module should be replaced with some already existing enums,
 // as long as they will provide `from_primitive` and `as_static_str` methods.
+//
+// Note that nothing calls them yet: conversion of the shared memory statistics
+// (`From<SharedMemory> for Statistics`) is not implemented, so the `shm` source
+// does not export any labels, consistent with the text parser or not.
+
+use crate::statistics::iana;
 
 /// Return query record type based on its number.
 ///
-/// Based on the `rdata_field_descriptors[]` array from the `unbound/rrdef.c`,
-/// used to match `ServerStats.qtype` field values to qtype names.
-///
-/// Implemented only partially right now with the most popular record types.
-///
-/// TODO: Complete the match.
+/// Shares the mnemonics table with the text parser (see `statistics::iana`),
+/// so the shared memory conversion will get the same labels, once it is implemented.
+/// Types without mnemonic should be represented as `TYPEnnn` by the caller.
 pub fn rr_type(value: usize) -> Option<&'static str> {
-    let type_ = match value {
-        1 => "A",
-        2 => "NS",
-        5 => "CNAME",
-        6 => "SOA",
-        10 => "NULL",
-        12 => "PTR",
-        15 => "MX",
-        16 => "TXT",
-        24 => "SIG",
-        25 => "KEY",
-        28 => "AAAA",
-        33 => "SRV",
-        35 => "NAPTR",
-        41 => "OPT",
-        43 => "DS",
-        44 => "SSHFP",
-        46 => "RRSIG",
-        47 => "NSEC",
-        48 => "DNSKEY",
-        50 => "NSEC3",
-        51 => "NSEC3PARAM",
-        52 => "TLSA",
-        61 => "OPENPGPKEY",
-        255 => "ANY",
-        252 => "AXFR",
-        257 => "CAA",
-        _ => return None,
-    };
+    if value > usize::from(u16::MAX) {
+        return None;
+    }
 
-    Some(type_)
+    iana::rtype_mnemonic(value as u16)
 }
 
 pub fn rr_class(value: usize) -> Option<&'static str> {
-    let class = match value {
-        1 => "IN",
-        3 => "CH",
-        4 => "HS",
-        254 => "NONE",
-        255 => "ANY",
-        _ => return None,
-    };
+    if value > usize::from(u16::MAX) {
+        return None;
+    }
 
-    Some(class)
+    iana::class_mnemonic(value as u16)
 }
 
 pub fn rr_opcode(value: usize) -> Option<&'static str> {
-    let class = match value {
-        0 => "QUERY",
-        1 => "IQUERY",
-        2 => "STATUS",
-        4 => "NOTIFY",
-        5 => "UPDATE",
-        _ => return None,
-    };
+    if value > usize::from(u8::MAX) {
+        return None;
+    }
 
-    Some(class)
+    iana::opcode_mnemonic(value as u8)
 }
 
 pub fn rr_rcode(value: usize) -> Option<&'static str> {
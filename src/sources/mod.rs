@@ -5,12 +5,14 @@ use crate::Statistics;
 mod control;
 #[cfg(unix)]
 mod memory;
+mod pool;
 
 #[cfg(unix)]
 pub use self::control::UdsTransport;
 pub use self::control::{RemoteControlSource, TextTransport, TlsTransport};
 #[cfg(unix)]
 pub use self::memory::SharedMemorySource;
+pub use self::pool::PoolSource;
 
 /// Source to fetch `unbound` statistics from.
 ///
@@ -0,0 +1,93 @@
+use std::io;
+
+use futures_util::future;
+
+use super::Source;
+use crate::Statistics;
+
+/// Data source which merges statistics of multiple `unbound` instances,
+/// ex. resolver pool behind the same anycast address.
+///
+/// Members are named (with their address, for example), so the errors are telling
+/// which one of them has failed and per-instance statistics can be fetched too.
+#[derive(Default)]
+pub struct PoolSource {
+    members: Vec<(String, Box<dyn Source + Send + Sync + 'static>)>,
+}
+
+impl PoolSource {
+    pub fn new() -> PoolSource {
+        PoolSource::default()
+    }
+
+    pub fn push<N>(&mut self, name: N, source: Box<dyn Source + Send + Sync + 'static>)
+    where
+        N: Into<String>,
+    {
+        self.members.push((name.into(), source))
+    }
+
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+    /// Find pool member by its name.
+    pub fn member(&self, name: &str) -> Option<&(dyn Source + Send + Sync + 'static)> {
+        self.members
+            .iter()
+            .find(|(member, _)| member == name)
+            .map(|(_, source)| source.as_ref())
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.members.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// Fetch statistics from all members concurrently, without merging them.
+    ///
+    /// Results are in the members order; failure of one member does not affect the others.
+    pub async fn observe_members(&self) -> Vec<(&str, io::Result<Statistics>)> {
+        let observations = self.members.iter().map(|(name, source)| async move {
+            (name.as_str(), source.observe().await.map_err(|e| member_error(name, e)))
+        });
+
+        future::join_all(observations).await
+    }
+}
+
+#[async_trait::async_trait]
+impl Source for PoolSource {
+    /// Pool is healthy only if all its members are.
+    async fn healthcheck(&self) -> io::Result<()> {
+        let checks = self
+            .members
+            .iter()
+            .map(|(name, source)| async move { source.healthcheck().await.map_err(|e| member_error(name, e)) });
+
+        future::join_all(checks).await.into_iter().collect()
+    }
+
+    /// Statistics are fetched from all members concurrently and merged with `Statistics::merge`.
+    ///
+    /// Observation fails if any member fails, as the merged counters would go backwards otherwise.
+    async fn observe(&self) -> io::Result<Statistics> {
+        let observations = self
+            .members
+            .iter()
+            .map(|(name, source)| async move { source.observe().await.map_err(|e| member_error(name, e)) });
+
+        future::join_all(observations)
+            .await
+            .into_iter()
+            .collect::<io::Result<Vec<_>>>()
+            .map(|statistics| statistics.iter().sum())
+    }
+}
+
+fn member_error(name: &str, e: io::Error) -> io::Error {
+    io::Error::new(e.kind(), format!("{}: {}", name, e))
+}
@@ -0,0 +1,653 @@
+//! Difference between two statistics snapshots.
+//!
+//! All structs are destructured explicitly in here (without the `..` rest pattern),
+//! so any new field added to them will fail the compilation until it is decided
+//! whether it is a counter or a gauge.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::time::Duration;
+
+use domain::base::iana::{Class, Opcode, Rcode, Rtype};
+
+use super::{Flags, Histogram, RpzAction, Statistics, Thread};
+
+/// Difference between two [`Statistics`] snapshots, see [`Statistics::delta`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatisticsDelta {
+    /// Time passed between the snapshots.
+    pub interval: Duration,
+    /// `true` if `unbound` was restarted or its statistics were reset between the snapshots.
+    ///
+    /// In that case counters increase is counted from zero and `interval`
+    /// is the time passed since the reset.
+    pub reset: bool,
+    /// Counters contain their increase between the snapshots (histogram buckets included),
+    /// gauges (memory usage, cache sizes, request list and recursion time values) are
+    /// taken as is from the newer snapshot.
+    pub increase: Statistics,
+}
+
+impl StatisticsDelta {
+    /// Per-second rate of some counter `increase`, ex. `delta.per_second(delta.increase.total.num_queries)`.
+    ///
+    /// Returns zero if the interval is zero.
+    pub fn per_second(&self, increase: u64) -> f64 {
+        let secs = self.interval.as_secs_f64();
+        if secs > 0.0 {
+            increase as f64 / secs
+        } else {
+            0.0
+        }
+    }
+
+    /// Per-second rates for all the entries of some counters map, ex. `delta.increase.query_types`.
+    pub fn per_second_map<K>(&self, increase: &HashMap<K, u64>) -> HashMap<K, f64>
+    where
+        K: Eq + Hash + Clone,
+    {
+        increase
+            .iter()
+            .map(|(key, value)| (key.clone(), self.per_second(*value)))
+            .collect()
+    }
+
+    /// Per-second rates of all the counters.
+    pub fn rates(&self) -> StatisticsRates {
+        let Statistics {
+            total,
+            threads,
+            time: _,
+            cache: _,
+            modules: _,
+            cache_count: _,
+            http: _,
+            flags,
+            query_opcodes,
+            query_types,
+            query_types_other,
+            query_classes,
+            query_classes_other,
+            answer_rcodes,
+            query_aggressive,
+            rpz_actions,
+            answer_ede,
+            histogram,
+            mem_streamwait: _,
+            num_query_tcp,
+            num_query_tcp_out,
+            num_query_tls,
+            num_query_tls_resume,
+            num_query_ipv6,
+            num_query_edns_present,
+            num_query_edns_do,
+            num_query_rate_limited,
+            num_query_https,
+            num_answer_secure,
+            num_answer_bogus,
+            num_rrset_bogus,
+            num_unwanted_queries,
+            num_unwanted_replies,
+            num_query_dnscrypt_shared_secret_cache_miss,
+            num_query_dnscrypt_replay,
+            num_query_authzone_up,
+            num_query_authzone_down,
+            num_query_subnet,
+            num_query_subnet_cache,
+            num_query_cachedb,
+            unknown: _,
+            diagnostics: _,
+        } = &self.increase;
+
+        StatisticsRates {
+            total: self.thread_rates(total),
+            threads: threads.iter().map(|thread| self.thread_rates(thread)).collect(),
+            flags: self.flags_rates(flags),
+            query_opcodes: self.per_second_map(query_opcodes),
+            query_types: self.per_second_map(query_types),
+            query_types_other: self.per_second(*query_types_other),
+            query_classes: self.per_second_map(query_classes),
+            query_classes_other: self.per_second(*query_classes_other),
+            answer_rcodes: self.per_second_map(answer_rcodes),
+            query_aggressive: self.per_second_map(query_aggressive),
+            rpz_actions: self.per_second_map(rpz_actions),
+            answer_ede: self.per_second_map(answer_ede),
+            histogram: histogram
+                .raw_buckets()
+                .map(|(le, count)| (le, self.per_second(count)))
+                .collect(),
+            num_query_tcp: self.per_second(*num_query_tcp),
+            num_query_tcp_out: self.per_second(*num_query_tcp_out),
+            num_query_tls: self.per_second(*num_query_tls),
+            num_query_tls_resume: self.per_second(*num_query_tls_resume),
+            num_query_ipv6: self.per_second(*num_query_ipv6),
+            num_query_edns_present: self.per_second(*num_query_edns_present),
+            num_query_edns_do: self.per_second(*num_query_edns_do),
+            num_query_rate_limited: self.per_second(*num_query_rate_limited),
+            num_query_https: self.per_second(*num_query_https),
+            num_answer_secure: self.per_second(*num_answer_secure),
+            num_answer_bogus: self.per_second(*num_answer_bogus),
+            num_rrset_bogus: self.per_second(*num_rrset_bogus),
+            num_unwanted_queries: self.per_second(*num_unwanted_queries),
+            num_unwanted_replies: self.per_second(*num_unwanted_replies),
+            num_query_dnscrypt_shared_secret_cache_miss: self.per_second(*num_query_dnscrypt_shared_secret_cache_miss),
+            num_query_dnscrypt_replay: self.per_second(*num_query_dnscrypt_replay),
+            num_query_authzone_up: self.per_second(*num_query_authzone_up),
+            num_query_authzone_down: self.per_second(*num_query_authzone_down),
+            num_query_subnet: self.per_second(*num_query_subnet),
+            num_query_subnet_cache: self.per_second(*num_query_subnet_cache),
+            num_query_cachedb: self.per_second(*num_query_cachedb),
+        }
+    }
+
+    fn thread_rates(&self, thread: &Thread) -> ThreadRates {
+        let Thread {
+            num_queries,
+            num_queries_ip_ratelimited,
+            num_cache_hits,
+            num_cache_miss,
+            num_prefetch,
+            num_zero_ttl,
+            num_recursive_replies,
+            num_dnscrypt_crypted,
+            num_dnscrypt_cert,
+            num_dnscrypt_cleartext,
+            num_dnscrypt_malformed,
+            requestlist_avg: _,
+            requestlist_max: _,
+            requestlist_overwritten,
+            requestlist_exceeded,
+            requestlist_current_all: _,
+            requestlist_current_user: _,
+            recursion_time_avg: _,
+            recursion_time_median: _,
+            tcp_usage: _,
+            answer_rcode,
+        } = thread;
+
+        ThreadRates {
+            num_queries: self.per_second(*num_queries),
+            num_queries_ip_ratelimited: self.per_second(*num_queries_ip_ratelimited),
+            num_cache_hits: self.per_second(*num_cache_hits),
+            num_cache_miss: self.per_second(*num_cache_miss),
+            num_prefetch: self.per_second(*num_prefetch),
+            num_zero_ttl: self.per_second(*num_zero_ttl),
+            num_recursive_replies: self.per_second(*num_recursive_replies),
+            num_dnscrypt_crypted: self.per_second(*num_dnscrypt_crypted),
+            num_dnscrypt_cert: self.per_second(*num_dnscrypt_cert),
+            num_dnscrypt_cleartext: self.per_second(*num_dnscrypt_cleartext),
+            num_dnscrypt_malformed: self.per_second(*num_dnscrypt_malformed),
+            requestlist_overwritten: self.per_second(*requestlist_overwritten),
+            requestlist_exceeded: self.per_second(*requestlist_exceeded),
+            answer_rcode: self.per_second_map(answer_rcode),
+        }
+    }
+
+    fn flags_rates(&self, flags: &Flags) -> FlagsRates {
+        let Flags {
+            qr,
+            aa,
+            tc,
+            rd,
+            ra,
+            z,
+            ad,
+            cd,
+        } = flags;
+
+        FlagsRates {
+            qr: self.per_second(*qr),
+            aa: self.per_second(*aa),
+            tc: self.per_second(*tc),
+            rd: self.per_second(*rd),
+            ra: self.per_second(*ra),
+            z: self.per_second(*z),
+            ad: self.per_second(*ad),
+            cd: self.per_second(*cd),
+        }
+    }
+}
+
+/// Per-second rates of the `Statistics` counters, see [`StatisticsDelta::rates`].
+///
+/// Fields are named after the `Statistics` ones; gauges and raw values are not included.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct StatisticsRates {
+    pub total: ThreadRates,
+    pub threads: Vec<ThreadRates>,
+    pub flags: FlagsRates,
+    pub query_opcodes: HashMap<Opcode, f64>,
+    pub query_types: HashMap<Rtype, f64>,
+    pub query_types_other: f64,
+    pub query_classes: HashMap<Class, f64>,
+    pub query_classes_other: f64,
+    pub answer_rcodes: HashMap<Rcode, f64>,
+    pub query_aggressive: HashMap<Rcode, f64>,
+    pub rpz_actions: HashMap<RpzAction, f64>,
+    pub answer_ede: HashMap<u16, f64>,
+    /// Rates of the response time histogram buckets by their upper bounds (not cumulative).
+    pub histogram: Vec<(Duration, f64)>,
+    pub num_query_tcp: f64,
+    pub num_query_tcp_out: f64,
+    pub num_query_tls: f64,
+    pub num_query_tls_resume: f64,
+    pub num_query_ipv6: f64,
+    pub num_query_edns_present: f64,
+    pub num_query_edns_do: f64,
+    pub num_query_rate_limited: f64,
+    pub num_query_https: f64,
+    pub num_answer_secure: f64,
+    pub num_answer_bogus: f64,
+    pub num_rrset_bogus: f64,
+    pub num_unwanted_queries: f64,
+    pub num_unwanted_replies: f64,
+    pub num_query_dnscrypt_shared_secret_cache_miss: f64,
+    pub num_query_dnscrypt_replay: f64,
+    pub num_query_authzone_up: f64,
+    pub num_query_authzone_down: f64,
+    pub num_query_subnet: f64,
+    pub num_query_subnet_cache: f64,
+    pub num_query_cachedb: f64,
+}
+
+/// Per-second rates of the `Thread` counters.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ThreadRates {
+    pub num_queries: f64,
+    pub num_queries_ip_ratelimited: f64,
+    pub num_cache_hits: f64,
+    pub num_cache_miss: f64,
+    pub num_prefetch: f64,
+    pub num_zero_ttl: f64,
+    pub num_recursive_replies: f64,
+    pub num_dnscrypt_crypted: f64,
+    pub num_dnscrypt_cert: f64,
+    pub num_dnscrypt_cleartext: f64,
+    pub num_dnscrypt_malformed: f64,
+    pub requestlist_overwritten: f64,
+    pub requestlist_exceeded: f64,
+    pub answer_rcode: HashMap<Rcode, f64>,
+}
+
+/// Per-second rates of the `Flags` counters.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct FlagsRates {
+    pub qr: f64,
+    pub aa: f64,
+    pub tc: f64,
+    pub rd: f64,
+    pub ra: f64,
+    pub z: f64,
+    pub ad: f64,
+    pub cd: f64,
+}
+
+impl Statistics {
+    /// Compute the difference between `self` and some `previous` snapshot of the same `unbound` instance.
+    ///
+    /// Interval is calculated from the `time.now` values; if they are not available
+    /// or the reset was detected, `time.elapsed` of the `self` is used instead.
+    pub fn delta(&self, previous: &Statistics) -> StatisticsDelta {
+        // Restart is obvious, but statistics reset via `unbound-control stats`
+        // can be noticed only by the counters decrease.
+        let reset = self.time.up < previous.time.up || self.total.num_queries < previous.total.num_queries;
+
+        match self.diff(previous, reset) {
+            // Some counter went backwards, all the counters should be treated as reset ones then
+            delta if delta.reset && !reset => self.diff(previous, true),
+            delta => delta,
+        }
+    }
+
+    fn diff(&self, previous: &Statistics, reset: bool) -> StatisticsDelta {
+        let mut diff = Diff { reset };
+
+        let Statistics {
+            total,
+            threads,
+            time,
+            cache,
+            modules,
+            cache_count,
+            http,
+            flags,
+            query_opcodes,
+            query_types,
+            query_types_other,
+            query_classes,
+            query_classes_other,
+            answer_rcodes,
+            query_aggressive,
+            rpz_actions,
+            answer_ede,
+            histogram,
+            mem_streamwait,
+            num_query_tcp,
+            num_query_tcp_out,
+            num_query_tls,
+            num_query_tls_resume,
+            num_query_ipv6,
+            num_query_edns_present,
+            num_query_edns_do,
+            num_query_rate_limited,
+            num_query_https,
+            num_answer_secure,
+            num_answer_bogus,
+            num_rrset_bogus,
+            num_unwanted_queries,
+            num_unwanted_replies,
+            num_query_dnscrypt_shared_secret_cache_miss,
+            num_query_dnscrypt_replay,
+            num_query_authzone_up,
+            num_query_authzone_down,
+            num_query_subnet,
+            num_query_subnet_cache,
+            num_query_cachedb,
+            unknown,
+            diagnostics: _,
+        } = self;
+
+        let default_thread = Thread::default();
+        let increase = Statistics {
+            total: diff.thread(total, &previous.total),
+            threads: threads
+                .iter()
+                .enumerate()
+                .map(|(idx, thread)| diff.thread(thread, previous.threads.get(idx).unwrap_or(&default_thread)))
+                .collect(),
+            time: time.clone(),
+            cache: cache.clone(),
+            modules: modules.clone(),
+            cache_count: cache_count.clone(),
+            http: http.clone(),
+            flags: diff.flags(flags, &previous.flags),
+            query_opcodes: diff.map(query_opcodes, &previous.query_opcodes),
+            query_types: diff.map(query_types, &previous.query_types),
+            query_types_other: diff.counter(*query_types_other, previous.query_types_other),
+            query_classes: diff.map(query_classes, &previous.query_classes),
+            query_classes_other: diff.counter(*query_classes_other, previous.query_classes_other),
+            answer_rcodes: diff.map(answer_rcodes, &previous.answer_rcodes),
+            query_aggressive: diff.map(query_aggressive, &previous.query_aggressive),
+            rpz_actions: diff.map(rpz_actions, &previous.rpz_actions),
+            answer_ede: diff.map(answer_ede, &previous.answer_ede),
+            histogram: diff.histogram(histogram, &previous.histogram),
+            mem_streamwait: *mem_streamwait,
+            num_query_tcp: diff.counter(*num_query_tcp, previous.num_query_tcp),
+            num_query_tcp_out: diff.counter(*num_query_tcp_out, previous.num_query_tcp_out),
+            num_query_tls: diff.counter(*num_query_tls, previous.num_query_tls),
+            num_query_tls_resume: diff.counter(*num_query_tls_resume, previous.num_query_tls_resume),
+            num_query_ipv6: diff.counter(*num_query_ipv6, previous.num_query_ipv6),
+            num_query_edns_present: diff.counter(*num_query_edns_present, previous.num_query_edns_present),
+            num_query_edns_do: diff.counter(*num_query_edns_do, previous.num_query_edns_do),
+            num_query_rate_limited: diff.counter(*num_query_rate_limited, previous.num_query_rate_limited),
+            num_query_https: diff.counter(*num_query_https, previous.num_query_https),
+            num_answer_secure: diff.counter(*num_answer_secure, previous.num_answer_secure),
+            num_answer_bogus: diff.counter(*num_answer_bogus, previous.num_answer_bogus),
+            num_rrset_bogus: diff.counter(*num_rrset_bogus, previous.num_rrset_bogus),
+            num_unwanted_queries: diff.counter(*num_unwanted_queries, previous.num_unwanted_queries),
+            num_unwanted_replies: diff.counter(*num_unwanted_replies, previous.num_unwanted_replies),
+            num_query_dnscrypt_shared_secret_cache_miss: diff.counter(
+                *num_query_dnscrypt_shared_secret_cache_miss,
+                previous.num_query_dnscrypt_shared_secret_cache_miss,
+            ),
+            num_query_dnscrypt_replay: diff.counter(*num_query_dnscrypt_replay, previous.num_query_dnscrypt_replay),
+            num_query_authzone_up: diff.counter(*num_query_authzone_up, previous.num_query_authzone_up),
+            num_query_authzone_down: diff.counter(*num_query_authzone_down, previous.num_query_authzone_down),
+            num_query_subnet: diff.counter(*num_query_subnet, previous.num_query_subnet),
+            num_query_subnet_cache: diff.counter(*num_query_subnet_cache, previous.num_query_subnet_cache),
+            num_query_cachedb: diff.counter(*num_query_cachedb, previous.num_query_cachedb),
+            // Raw values, there is no way to tell if they are counters
+            unknown: unknown.clone(),
+            diagnostics: Vec::new(),
+        };
+
+        let interval = match self.time.now.checked_sub(previous.time.now) {
+            Some(interval) if !diff.reset && interval > Duration::from_secs(0) => interval,
+            _ => self.time.elapsed,
+        };
+
+        StatisticsDelta {
+            interval,
+            reset: diff.reset,
+            increase,
+        }
+    }
+}
+
+/// Counters difference calculation, which keeps track of the reset detection.
+struct Diff {
+    reset: bool,
+}
+
+impl Diff {
+    fn counter(&mut self, current: u64, previous: u64) -> u64 {
+        if self.reset {
+            current
+        } else if current < previous {
+            // Counter went backwards, it is possible only if it was reset
+            self.reset = true;
+            current
+        } else {
+            current - previous
+        }
+    }
+
+    fn map<K>(&mut self, current: &HashMap<K, u64>, previous: &HashMap<K, u64>) -> HashMap<K, u64>
+    where
+        K: Eq + Hash + Clone,
+    {
+        current
+            .iter()
+            .map(|(key, value)| {
+                let previous = previous.get(key).copied().unwrap_or_default();
+                (key.clone(), self.counter(*value, previous))
+            })
+            .collect()
+    }
+
+    fn thread(&mut self, current: &Thread, previous: &Thread) -> Thread {
+        let Thread {
+            num_queries,
+            num_queries_ip_ratelimited,
+            num_cache_hits,
+            num_cache_miss,
+            num_prefetch,
+            num_zero_ttl,
+            num_recursive_replies,
+            num_dnscrypt_crypted,
+            num_dnscrypt_cert,
+            num_dnscrypt_cleartext,
+            num_dnscrypt_malformed,
+            requestlist_avg,
+            requestlist_max,
+            requestlist_overwritten,
+            requestlist_exceeded,
+            requestlist_current_all,
+            requestlist_current_user,
+            recursion_time_avg,
+            recursion_time_median,
+            tcp_usage,
+            answer_rcode,
+        } = current;
+
+        Thread {
+            num_queries: self.counter(*num_queries, previous.num_queries),
+            num_queries_ip_ratelimited: self.counter(*num_queries_ip_ratelimited, previous.num_queries_ip_ratelimited),
+            num_cache_hits: self.counter(*num_cache_hits, previous.num_cache_hits),
+            num_cache_miss: self.counter(*num_cache_miss, previous.num_cache_miss),
+            num_prefetch: self.counter(*num_prefetch, previous.num_prefetch),
+            num_zero_ttl: self.counter(*num_zero_ttl, previous.num_zero_ttl),
+            num_recursive_replies: self.counter(*num_recursive_replies, previous.num_recursive_replies),
+            num_dnscrypt_crypted: self.counter(*num_dnscrypt_crypted, previous.num_dnscrypt_crypted),
+            num_dnscrypt_cert: self.counter(*num_dnscrypt_cert, previous.num_dnscrypt_cert),
+            num_dnscrypt_cleartext: self.counter(*num_dnscrypt_cleartext, previous.num_dnscrypt_cleartext),
+            num_dnscrypt_malformed: self.counter(*num_dnscrypt_malformed, previous.num_dnscrypt_malformed),
+            requestlist_avg: *requestlist_avg,
+            requestlist_max: *requestlist_max,
+            requestlist_overwritten: self.counter(*requestlist_overwritten, previous.requestlist_overwritten),
+            requestlist_exceeded: self.counter(*requestlist_exceeded, previous.requestlist_exceeded),
+            requestlist_current_all: *requestlist_current_all,
+            requestlist_current_user: *requestlist_current_user,
+            recursion_time_avg: *recursion_time_avg,
+            recursion_time_median: *recursion_time_median,
+            tcp_usage: *tcp_usage,
+            answer_rcode: self.map(answer_rcode, &previous.answer_rcode),
+        }
+    }
+
+    fn flags(&mut self, current: &Flags, previous: &Flags) -> Flags {
+        let Flags {
+            qr,
+            aa,
+            tc,
+            rd,
+            ra,
+            z,
+            ad,
+            cd,
+        } = current;
+
+        Flags {
+            qr: self.counter(*qr, previous.qr),
+            aa: self.counter(*aa, previous.aa),
+            tc: self.counter(*tc, previous.tc),
+            rd: self.counter(*rd, previous.rd),
+            ra: self.counter(*ra, previous.ra),
+            z: self.counter(*z, previous.z),
+            ad: self.counter(*ad, previous.ad),
+            cd: self.counter(*cd, previous.cd),
+        }
+    }
+
+    /// Buckets are matched by their upper bounds; average is recalculated for the interval only.
+    fn histogram(&mut self, current: &Histogram, previous: &Histogram) -> Histogram {
+        let mut increase = Histogram::default();
+        for (le, count) in current.raw_buckets() {
+            let previous = previous
+                .raw_buckets()
+                .find(|(previous_le, _)| *previous_le == le)
+                .map_or(0, |(_, count)| count);
+            increase.push(le, self.counter(count, previous));
+        }
+
+        let count = increase.count();
+        let sum = if self.reset {
+            current.sum()
+        } else {
+            current.sum() - previous.sum()
+        };
+        if count > 0 && sum.is_finite() {
+            *increase.average_mut() = sum / count as f64;
+        }
+
+        increase
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+    use std::time::Duration;
+
+    use claim::assert_ok;
+    use domain::base::iana::Rtype;
+
+    use crate::statistics::{Histogram, Statistics};
+
+    static STATS: &str = include_str!("../../assets/test_text_stats.txt");
+
+    #[test]
+    fn test_delta() {
+        let previous = assert_ok!(Statistics::from_str(STATS));
+        let mut current = assert_ok!(Statistics::from_str(STATS));
+        current.time.now += Duration::from_secs(10);
+        current.time.up += Duration::from_secs(10);
+        current.total.num_queries += 50;
+        current.total.requestlist_max = 3;
+        current.threads[1].num_cache_hits += 20;
+        current.flags.rd += 30;
+        current.cache.rrset = 1;
+        *current.query_types.entry(Rtype::A).or_default() += 40;
+        let _ = current.query_types.insert(Rtype::from_int(257), 5);
+
+        let delta = current.delta(&previous);
+        assert!(!delta.reset);
+        assert_eq!(delta.interval, Duration::from_secs(10));
+
+        assert_eq!(delta.increase.total.num_queries, 50);
+        approx::assert_relative_eq!(delta.per_second(delta.increase.total.num_queries), 5.0);
+        assert_eq!(delta.increase.total.requestlist_max, 3);
+        assert_eq!(delta.increase.threads[0].num_cache_hits, 0);
+        assert_eq!(delta.increase.threads[1].num_cache_hits, 20);
+        assert_eq!(delta.increase.flags.rd, 30);
+        assert_eq!(delta.increase.cache.rrset, 1);
+        assert_eq!(delta.increase.query_types.get(&Rtype::A), Some(&40));
+        assert_eq!(delta.increase.query_types.get(&Rtype::from_int(257)), Some(&5));
+        assert_eq!(delta.increase.query_types.get(&Rtype::Aaaa), Some(&0));
+
+        let rates = delta.per_second_map(&delta.increase.query_types);
+        approx::assert_relative_eq!(rates[&Rtype::A], 4.0);
+
+        let rates = delta.rates();
+        approx::assert_relative_eq!(rates.total.num_queries, 5.0);
+        approx::assert_relative_eq!(rates.threads[1].num_cache_hits, 2.0);
+        approx::assert_relative_eq!(rates.flags.rd, 3.0);
+        approx::assert_relative_eq!(rates.query_types[&Rtype::from_int(257)], 0.5);
+        approx::assert_relative_eq!(rates.num_query_tcp, 0.0);
+
+        assert_eq!(delta.increase.histogram.count(), 0);
+        approx::assert_relative_eq!(delta.increase.histogram.sum(), 0.0);
+    }
+
+    #[test]
+    fn test_delta_histogram() {
+        let previous = assert_ok!(Statistics::from_str(STATS));
+        let mut current = previous.clone();
+        current.time.now += Duration::from_secs(2);
+        current.total.num_queries += 4;
+        current.histogram = Histogram::default();
+        for (le, count) in previous.histogram.raw_buckets() {
+            let count = if le == Duration::from_micros(131_072) {
+                count + 4
+            } else {
+                count
+            };
+            current.histogram.push(le, count);
+        }
+        // All new queries took 0.1 second
+        *current.histogram.average_mut() = (previous.histogram.sum() + 0.4) / (previous.histogram.count() + 4) as f64;
+
+        let delta = current.delta(&previous);
+        assert!(!delta.reset);
+        assert_eq!(delta.increase.histogram.count(), 4);
+        approx::assert_relative_eq!(delta.increase.histogram.sum(), 0.4, epsilon = 1e-9);
+    }
+
+    #[test]
+    fn test_delta_reset() {
+        let previous = assert_ok!(Statistics::from_str(STATS));
+        let mut current = assert_ok!(Statistics::from_str(STATS));
+        current.time.now += Duration::from_secs(60);
+        current.time.up = Duration::from_secs(30);
+        current.time.elapsed = Duration::from_secs(30);
+        current.total.num_queries = 15;
+
+        let delta = current.delta(&previous);
+        assert!(delta.reset);
+        assert_eq!(delta.interval, Duration::from_secs(30));
+        assert_eq!(delta.increase.total.num_queries, 15);
+        approx::assert_relative_eq!(delta.per_second(delta.increase.total.num_queries), 0.5);
+        assert_eq!(delta.increase.num_answer_secure, current.num_answer_secure);
+
+        // Reset is detected by the counters too
+        let mut current = assert_ok!(Statistics::from_str(STATS));
+        current.time.now += Duration::from_secs(60);
+        current.time.up += Duration::from_secs(60);
+        current.threads[0].num_queries += 10;
+        current.num_rrset_bogus = 0;
+        let delta = current.delta(&previous);
+        assert!(delta.reset);
+        // Counters before the one which went backwards are not diffed either
+        assert_eq!(delta.increase.threads[0].num_queries, current.threads[0].num_queries);
+    }
+}
@@ -1,13 +1,23 @@
 use std::num;
 use std::time::Duration;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_crate::Serialize, serde_crate::Deserialize),
+    serde(crate = "serde_crate")
+)]
 struct InnerBucket {
     le: Duration,
     count: u64,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_crate::Serialize, serde_crate::Deserialize),
+    serde(crate = "serde_crate", default)
+)]
 pub struct Histogram {
     buckets: Vec<InnerBucket>,
     average: f64,
@@ -21,6 +31,10 @@ impl Histogram {
         }
     }
 
+    pub fn average(&self) -> f64 {
+        self.average
+    }
+
     pub fn average_mut(&mut self) -> &mut f64 {
         &mut self.average
     }
@@ -41,6 +55,73 @@ impl Histogram {
             .0
     }
 
+    /// Upper bounds and non-cumulative counts of the buckets in the order they were pushed.
+    pub fn raw_buckets(&self) -> impl Iterator<Item = (Duration, u64)> + '_ {
+        self.buckets.iter().map(|bucket| (bucket.le, bucket.count))
+    }
+
+    /// Estimate the `q`-quantile (`0.0 <= q <= 1.0`) of the observed values, in seconds.
+    ///
+    /// Same as the Prometheus `histogram_quantile` function does, values are assumed to be
+    /// distributed linearly inside of the bucket, where the lower bound of the bucket is
+    /// the upper bound of the previous one (or zero for the first bucket).
+    /// As `unbound` buckets are exponential, estimation error is up to the half of the bucket width.
+    ///
+    /// Returns `None` if histogram is empty or `q` is out of range.
+    pub fn quantile(&self, q: f64) -> Option<f64> {
+        let count = self.count();
+        if count == 0 || !(0.0..=1.0).contains(&q) {
+            return None;
+        }
+
+        let mut buckets = self.raw_buckets().collect::<Vec<_>>();
+        buckets.sort_unstable_by_key(|(le, _)| *le);
+
+        let rank = q * count as f64;
+        let mut lower = 0.0;
+        let mut seen = 0u64;
+        for (le, bucket_count) in buckets {
+            let upper = le.as_secs_f64();
+            // Empty buckets are skipped, so the `q == 0.0` is the lower bound of the first non-empty one
+            if bucket_count > 0 && seen as f64 + bucket_count as f64 >= rank {
+                let position = (rank - seen as f64) / bucket_count as f64;
+                return Some(lower + (upper - lower) * position);
+            }
+
+            seen = seen.wrapping_add(bucket_count);
+            lower = upper;
+        }
+
+        // Unreachable unless the total count has wrapped around
+        Some(lower)
+    }
+
+    /// Cumulative counts of the observations for the custom bucket upper `bounds`,
+    /// followed by the `Bucket::Inf` with the total count.
+    ///
+    /// Each `unbound` bucket is counted for all the bounds which are not lower than its upper bound,
+    /// so the result is exact if every bound is equal to some `unbound` bucket upper bound.
+    /// Otherwise it is an approximation: observations of the bucket crossing the bound
+    /// are counted at the next bound only, as there is no way to tell how many of them are below it.
+    ///
+    /// `bounds` are expected to be sorted in the ascending order.
+    pub fn rebucket(&self, bounds: &[Duration]) -> Vec<Bucket> {
+        let mut buckets = bounds
+            .iter()
+            .map(|bound| {
+                let count = self
+                    .raw_buckets()
+                    .filter(|(le, _)| le <= bound)
+                    .map(|(_, count)| num::Wrapping(count))
+                    .sum::<num::Wrapping<u64>>();
+                Bucket::Le(*bound, count.0)
+            })
+            .collect::<Vec<_>>();
+        buckets.push(Bucket::Inf(self.count()));
+
+        buckets
+    }
+
     pub fn buckets(&mut self) -> Buckets<'_> {
         Buckets::new(&mut self.buckets)
     }
@@ -329,4 +410,78 @@ mod tests {
         assert_eq!(buckets.next(), Some(Bucket::Inf(7)));
         assert_eq!(buckets.next(), None);
     }
+
+    #[test]
+    fn test_quantile() {
+        let mut h = Histogram::default();
+        assert_eq!(h.quantile(0.5), None);
+
+        for (le, value) in [
+            (Duration::from_micros(32_768), 0),
+            (Duration::from_micros(65_536), 2),
+            (Duration::from_micros(131_072), 1),
+            (Duration::from_micros(262_144), 1),
+            (Duration::from_micros(524_288), 2),
+            (Duration::from_secs(1), 1),
+            (Duration::from_secs(2), 0),
+        ]
+        .iter()
+        {
+            h.push(*le, *value);
+        }
+
+        // 3.5th value is in the middle of the `0.131072..0.262144` bucket
+        approx::assert_relative_eq!(h.quantile(0.5).unwrap(), 0.196_608);
+        approx::assert_relative_eq!(h.quantile(1.0).unwrap(), 1.0);
+        // Lower bound of the first non-empty bucket
+        approx::assert_relative_eq!(h.quantile(0.0).unwrap(), 0.032_768);
+        approx::assert_relative_eq!(
+            h.quantile(0.9).unwrap(),
+            0.524_288 + (1.0 - 0.524_288) * 0.3,
+            epsilon = 1e-9
+        );
+
+        assert_eq!(h.quantile(1.5), None);
+        assert_eq!(h.quantile(-0.1), None);
+        assert_eq!(h.quantile(f64::NAN), None);
+    }
+
+    #[test]
+    fn test_rebucket() {
+        let mut h = Histogram::default();
+        for (le, value) in [
+            (Duration::from_micros(65_536), 2),
+            (Duration::from_micros(131_072), 1),
+            (Duration::from_micros(262_144), 1),
+            (Duration::from_micros(524_288), 2),
+            (Duration::from_secs(1), 1),
+            (Duration::from_secs(2), 3),
+        ]
+        .iter()
+        {
+            h.push(*le, *value);
+        }
+
+        // Aligned with the `unbound` buckets
+        assert_eq!(
+            h.rebucket(&[Duration::from_micros(131_072), Duration::from_secs(1)]),
+            vec![
+                Bucket::Le(Duration::from_micros(131_072), 3),
+                Bucket::Le(Duration::from_secs(1), 7),
+                Bucket::Inf(10),
+            ]
+        );
+
+        // `0.1` is crossing the `0.065536..0.131072` bucket, so it is counted at `0.5` only
+        assert_eq!(
+            h.rebucket(&[Duration::from_millis(100), Duration::from_millis(500)]),
+            vec![
+                Bucket::Le(Duration::from_millis(100), 2),
+                Bucket::Le(Duration::from_millis(500), 4),
+                Bucket::Inf(10),
+            ]
+        );
+
+        assert_eq!(h.rebucket(&[]), vec![Bucket::Inf(10)]);
+    }
 }
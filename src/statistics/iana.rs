@@ -0,0 +1,274 @@
+//! Textual representation of the DNS parameters as `unbound` prints them.
+//!
+//! `domain` crate enums are not aware of some record types (ex. `SVCB` and `HTTPS`)
+//! and are using slightly different mnemonics in some cases,
+//! so the mapping is done here in order to have the same labels
+//! no matter which data source was used to fetch the statistics
+//! (the `*_mnemonic` functions are reserved for the shared memory source,
+//! which does not convert the statistics yet).
+//!
+//! Values without mnemonic are represented in the RFC 3597 generic syntax,
+//! ex. `TYPE65534` or `CLASS5`, same as `unbound` does.
+
+use std::borrow::Cow;
+
+use domain::base::iana::{Class, Opcode, Rcode, Rtype};
+
+/// Record types mnemonics, based on the `rdata_field_descriptors[]` array from the `sldns/rrdef.c`.
+static RTYPES: &[(u16, &str)] = &[
+    (1, "A"),
+    (2, "NS"),
+    (3, "MD"),
+    (4, "MF"),
+    (5, "CNAME"),
+    (6, "SOA"),
+    (7, "MB"),
+    (8, "MG"),
+    (9, "MR"),
+    (10, "NULL"),
+    (11, "WKS"),
+    (12, "PTR"),
+    (13, "HINFO"),
+    (14, "MINFO"),
+    (15, "MX"),
+    (16, "TXT"),
+    (17, "RP"),
+    (18, "AFSDB"),
+    (19, "X25"),
+    (20, "ISDN"),
+    (21, "RT"),
+    (22, "NSAP"),
+    (23, "NSAP-PTR"),
+    (24, "SIG"),
+    (25, "KEY"),
+    (26, "PX"),
+    (27, "GPOS"),
+    (28, "AAAA"),
+    (29, "LOC"),
+    (30, "NXT"),
+    (31, "EID"),
+    (32, "NIMLOC"),
+    (33, "SRV"),
+    (34, "ATMA"),
+    (35, "NAPTR"),
+    (36, "KX"),
+    (37, "CERT"),
+    (38, "A6"),
+    (39, "DNAME"),
+    (40, "SINK"),
+    (41, "OPT"),
+    (42, "APL"),
+    (43, "DS"),
+    (44, "SSHFP"),
+    (45, "IPSECKEY"),
+    (46, "RRSIG"),
+    (47, "NSEC"),
+    (48, "DNSKEY"),
+    (49, "DHCID"),
+    (50, "NSEC3"),
+    (51, "NSEC3PARAM"),
+    (52, "TLSA"),
+    (53, "SMIMEA"),
+    (55, "HIP"),
+    (56, "NINFO"),
+    (57, "RKEY"),
+    (58, "TALINK"),
+    (59, "CDS"),
+    (60, "CDNSKEY"),
+    (61, "OPENPGPKEY"),
+    (62, "CSYNC"),
+    (63, "ZONEMD"),
+    (64, "SVCB"),
+    (65, "HTTPS"),
+    (99, "SPF"),
+    (100, "UINFO"),
+    (101, "UID"),
+    (102, "GID"),
+    (103, "UNSPEC"),
+    (104, "NID"),
+    (105, "L32"),
+    (106, "L64"),
+    (107, "LP"),
+    (108, "EUI48"),
+    (109, "EUI64"),
+    (249, "TKEY"),
+    (250, "TSIG"),
+    (251, "IXFR"),
+    (252, "AXFR"),
+    (253, "MAILB"),
+    (254, "MAILA"),
+    (255, "ANY"),
+    (256, "URI"),
+    (257, "CAA"),
+    (258, "AVC"),
+    (32768, "TA"),
+    (32769, "DLV"),
+];
+
+/// Classes mnemonics, based on the `sldns_rr_classes[]` array from the `sldns/wire2str.c`.
+static CLASSES: &[(u16, &str)] = &[(1, "IN"), (3, "CH"), (4, "HS"), (254, "NONE"), (255, "ANY")];
+
+/// Opcodes mnemonics, based on the `sldns_opcodes[]` array from the `sldns/wire2str.c`.
+static OPCODES: &[(u16, &str)] = &[(0, "QUERY"), (1, "IQUERY"), (2, "STATUS"), (4, "NOTIFY"), (5, "UPDATE")];
+
+/// Response codes mnemonics, based on the `sldns_rcodes[]` array from the `sldns/wire2str.c`.
+static RCODES: &[(u16, &str)] = &[
+    (0, "NOERROR"),
+    (1, "FORMERR"),
+    (2, "SERVFAIL"),
+    (3, "NXDOMAIN"),
+    (4, "NOTIMPL"),
+    (5, "REFUSED"),
+    (6, "YXDOMAIN"),
+    (7, "YXRRSET"),
+    (8, "NXRRSET"),
+    (9, "NOTAUTH"),
+    (10, "NOTZONE"),
+];
+
+fn mnemonic(table: &'static [(u16, &'static str)], value: u16) -> Option<&'static str> {
+    table.iter().find(|(int, _)| *int == value).map(|(_, name)| *name)
+}
+
+/// Parse either mnemonic from the `table` or the RFC 3597 generic syntax with the `prefix` given.
+fn from_str(table: &[(u16, &str)], prefix: &str, s: &str) -> Option<u16> {
+    if let Some((int, _)) = table.iter().find(|(_, name)| name.eq_ignore_ascii_case(s)) {
+        return Some(*int);
+    }
+
+    match s.get(..prefix.len()) {
+        Some(head) if head.eq_ignore_ascii_case(prefix) => {
+            let digits = &s[prefix.len()..];
+            // `u16::from_str` allows the leading `+` sign, which is not a valid generic syntax
+            if !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()) {
+                digits.parse().ok()
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
+
+fn to_str(table: &'static [(u16, &'static str)], prefix: &str, value: u16) -> Cow<'static, str> {
+    match mnemonic(table, value) {
+        Some(name) => Cow::Borrowed(name),
+        None => Cow::Owned(format!("{}{}", prefix, value)),
+    }
+}
+
+/// Return the record type mnemonic by its numeric value, if there is one.
+pub fn rtype_mnemonic(value: u16) -> Option<&'static str> {
+    mnemonic(RTYPES, value)
+}
+
+/// Return the class mnemonic by its numeric value, if there is one.
+pub fn class_mnemonic(value: u16) -> Option<&'static str> {
+    mnemonic(CLASSES, value)
+}
+
+/// Return the opcode mnemonic by its numeric value, if there is one.
+pub fn opcode_mnemonic(value: u8) -> Option<&'static str> {
+    mnemonic(OPCODES, u16::from(value))
+}
+
+pub fn parse_rtype(s: &str) -> Option<Rtype> {
+    from_str(RTYPES, "TYPE", s).map(Rtype::from_int)
+}
+
+pub fn parse_class(s: &str) -> Option<Class> {
+    from_str(CLASSES, "CLASS", s).map(Class::from_int)
+}
+
+pub fn parse_opcode(s: &str) -> Option<Opcode> {
+    match from_str(OPCODES, "OPCODE", s) {
+        Some(value) if value <= u16::from(u8::MAX) => Some(Opcode::from_int(value as u8)),
+        _ => None,
+    }
+}
+
+/// Response codes are 4 bits wide in the message header,
+/// `unbound` prints the ones without mnemonic as `RCODE11`.
+pub fn parse_rcode(s: &str) -> Option<Rcode> {
+    match from_str(RCODES, "RCODE", s) {
+        Some(value) if value <= 0x0F => Some(Rcode::from_int(value as u8)),
+        _ => None,
+    }
+}
+
+pub fn rtype_to_str(rtype: Rtype) -> Cow<'static, str> {
+    to_str(RTYPES, "TYPE", rtype.to_int())
+}
+
+pub fn class_to_str(class: Class) -> Cow<'static, str> {
+    to_str(CLASSES, "CLASS", class.to_int())
+}
+
+pub fn opcode_to_str(opcode: Opcode) -> Cow<'static, str> {
+    to_str(OPCODES, "OPCODE", u16::from(opcode.to_int()))
+}
+
+pub fn rcode_to_str(rcode: Rcode) -> Cow<'static, str> {
+    to_str(RCODES, "RCODE", u16::from(rcode.to_int()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rtype() {
+        assert_eq!(parse_rtype("A"), Some(Rtype::A));
+        assert_eq!(parse_rtype("aaaa"), Some(Rtype::Aaaa));
+        assert_eq!(parse_rtype("NSAP-PTR"), Some(Rtype::Nsapptr));
+        assert_eq!(parse_rtype("HTTPS"), Some(Rtype::from_int(65)));
+        assert_eq!(parse_rtype("TYPE65"), Some(Rtype::from_int(65)));
+        assert_eq!(parse_rtype("type64"), Some(Rtype::from_int(64)));
+        assert_eq!(parse_rtype("TYPE0"), Some(Rtype::from_int(0)));
+        assert_eq!(parse_rtype("TYPE65535"), Some(Rtype::from_int(65535)));
+        assert_eq!(parse_rtype("TYPE65536"), None);
+        assert_eq!(parse_rtype("TYPE+1"), None);
+        assert_eq!(parse_rtype("TYPE"), None);
+        assert_eq!(parse_rtype("WHATEVER"), None);
+
+        assert_eq!(rtype_to_str(Rtype::A), "A");
+        assert_eq!(rtype_to_str(Rtype::Nsapptr), "NSAP-PTR");
+        assert_eq!(rtype_to_str(Rtype::from_int(64)), "SVCB");
+        assert_eq!(rtype_to_str(Rtype::from_int(65)), "HTTPS");
+        assert_eq!(rtype_to_str(Rtype::from_int(96)), "TYPE96");
+    }
+
+    #[test]
+    fn test_class() {
+        assert_eq!(parse_class("IN"), Some(Class::In));
+        assert_eq!(parse_class("ANY"), Some(Class::Any));
+        assert_eq!(parse_class("CLASS0"), Some(Class::from_int(0)));
+        assert_eq!(parse_class("class5"), Some(Class::from_int(5)));
+        assert_eq!(parse_class("*"), None);
+
+        assert_eq!(class_to_str(Class::Any), "ANY");
+        assert_eq!(class_to_str(Class::from_int(5)), "CLASS5");
+    }
+
+    #[test]
+    fn test_opcode() {
+        assert_eq!(parse_opcode("QUERY"), Some(Opcode::Query));
+        assert_eq!(parse_opcode("OPCODE3"), Some(Opcode::from_int(3)));
+        assert_eq!(parse_opcode("OPCODE256"), None);
+
+        assert_eq!(opcode_to_str(Opcode::Notify), "NOTIFY");
+        assert_eq!(opcode_to_str(Opcode::from_int(3)), "OPCODE3");
+    }
+
+    #[test]
+    fn test_rcode() {
+        assert_eq!(parse_rcode("NOERROR"), Some(Rcode::NoError));
+        assert_eq!(parse_rcode("NOTIMPL"), Some(Rcode::NotImp));
+        assert_eq!(parse_rcode("RCODE11"), Some(Rcode::from_int(11)));
+        assert_eq!(parse_rcode("RCODE16"), None);
+        assert_eq!(parse_rcode("nodata"), None);
+
+        assert_eq!(rcode_to_str(Rcode::NXDomain), "NXDOMAIN");
+        assert_eq!(rcode_to_str(Rcode::from_int(11)), "RCODE11");
+    }
+}
@@ -0,0 +1,621 @@
+//! Merging statistics of multiple `unbound` instances into one snapshot.
+//!
+//! Same as in the `delta` module, all structs are destructured explicitly in here,
+//! so any new field will fail the compilation until it is decided how it should be merged.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::iter::Sum;
+
+use super::{Cache, CacheCounter, Flags, Histogram, Http, Modules, Statistics, Thread, Time};
+
+impl Statistics {
+    /// Merge `other` instance statistics into `self`, as if they were collected from one `unbound` instance.
+    ///
+    /// * counters and memory usage values are summed,
+    /// * histograms are merged bucket-wise (buckets are matched by their upper bounds),
+    /// * `requestlist_max` and time values are taking the maximum of both,
+    /// * averages are weighted by the number of the queries they were calculated for;
+    ///   `recursion_time_median` is weighted the same way, so it is an approximation only,
+    /// * threads of `other` are appended to the `self` ones, so they are re-numbered,
+    /// * unknown keys from `other` are added only if `self` does not have them already,
+    ///   as there is no way to tell how their values should be combined.
+    ///
+    /// Merging with the `Statistics::default()` leaves the statistics unchanged.
+    pub fn merge(&mut self, other: &Statistics) {
+        let Statistics {
+            total,
+            threads,
+            time,
+            cache,
+            modules,
+            cache_count,
+            http,
+            flags,
+            query_opcodes,
+            query_types,
+            query_types_other,
+            query_classes,
+            query_classes_other,
+            answer_rcodes,
+            query_aggressive,
+            rpz_actions,
+            answer_ede,
+            histogram,
+            mem_streamwait,
+            num_query_tcp,
+            num_query_tcp_out,
+            num_query_tls,
+            num_query_tls_resume,
+            num_query_ipv6,
+            num_query_edns_present,
+            num_query_edns_do,
+            num_query_rate_limited,
+            num_query_https,
+            num_answer_secure,
+            num_answer_bogus,
+            num_rrset_bogus,
+            num_unwanted_queries,
+            num_unwanted_replies,
+            num_query_dnscrypt_shared_secret_cache_miss,
+            num_query_dnscrypt_replay,
+            num_query_authzone_up,
+            num_query_authzone_down,
+            num_query_subnet,
+            num_query_subnet_cache,
+            num_query_cachedb,
+            unknown,
+            diagnostics,
+        } = other;
+
+        merge_thread(&mut self.total, total);
+        self.threads.extend(threads.iter().cloned());
+        merge_time(&mut self.time, time);
+        merge_cache(&mut self.cache, cache);
+        merge_modules(&mut self.modules, modules);
+        merge_cache_count(&mut self.cache_count, cache_count);
+        merge_http(&mut self.http, http);
+        merge_flags(&mut self.flags, flags);
+        merge_map(&mut self.query_opcodes, query_opcodes);
+        merge_map(&mut self.query_types, query_types);
+        add(&mut self.query_types_other, *query_types_other);
+        merge_map(&mut self.query_classes, query_classes);
+        add(&mut self.query_classes_other, *query_classes_other);
+        merge_map(&mut self.answer_rcodes, answer_rcodes);
+        merge_map(&mut self.query_aggressive, query_aggressive);
+        merge_map(&mut self.rpz_actions, rpz_actions);
+        merge_map(&mut self.answer_ede, answer_ede);
+        merge_histogram(&mut self.histogram, histogram);
+        add(&mut self.mem_streamwait, *mem_streamwait);
+        add(&mut self.num_query_tcp, *num_query_tcp);
+        add(&mut self.num_query_tcp_out, *num_query_tcp_out);
+        add(&mut self.num_query_tls, *num_query_tls);
+        add(&mut self.num_query_tls_resume, *num_query_tls_resume);
+        add(&mut self.num_query_ipv6, *num_query_ipv6);
+        add(&mut self.num_query_edns_present, *num_query_edns_present);
+        add(&mut self.num_query_edns_do, *num_query_edns_do);
+        add(&mut self.num_query_rate_limited, *num_query_rate_limited);
+        add(&mut self.num_query_https, *num_query_https);
+        add(&mut self.num_answer_secure, *num_answer_secure);
+        add(&mut self.num_answer_bogus, *num_answer_bogus);
+        add(&mut self.num_rrset_bogus, *num_rrset_bogus);
+        add(&mut self.num_unwanted_queries, *num_unwanted_queries);
+        add(&mut self.num_unwanted_replies, *num_unwanted_replies);
+        add(
+            &mut self.num_query_dnscrypt_shared_secret_cache_miss,
+            *num_query_dnscrypt_shared_secret_cache_miss,
+        );
+        add(&mut self.num_query_dnscrypt_replay, *num_query_dnscrypt_replay);
+        add(&mut self.num_query_authzone_up, *num_query_authzone_up);
+        add(&mut self.num_query_authzone_down, *num_query_authzone_down);
+        add(&mut self.num_query_subnet, *num_query_subnet);
+        add(&mut self.num_query_subnet_cache, *num_query_subnet_cache);
+        add(&mut self.num_query_cachedb, *num_query_cachedb);
+
+        for (key, value) in unknown {
+            if !self.unknown.iter().any(|(known, _)| known == key) {
+                self.unknown.push((key.clone(), value.clone()));
+            }
+        }
+        self.diagnostics.extend(diagnostics.iter().cloned());
+    }
+
+    /// Merge only the counters of the `other` instance statistics into `self`,
+    /// ex. the last observed statistics of the pool member which is not available anymore.
+    ///
+    /// Values describing the current state of `other` (time, memory usage, cache sizes,
+    /// request list sizes and TCP buffers) are outdated, so they are not merged at all.
+    /// Averages are merged same as by `Statistics::merge`, as they are describing the counted queries,
+    /// and threads of `other` are appended too, so the threads of the next instances are not re-numbered.
+    /// Unknown keys are not merged, as there is no way to tell if they are counters.
+    pub fn merge_counters(&mut self, other: &Statistics) {
+        let Statistics {
+            total,
+            threads,
+            time: _,
+            cache: _,
+            modules: _,
+            cache_count: _,
+            http: _,
+            flags,
+            query_opcodes,
+            query_types,
+            query_types_other,
+            query_classes,
+            query_classes_other,
+            answer_rcodes,
+            query_aggressive,
+            rpz_actions,
+            answer_ede,
+            histogram,
+            mem_streamwait: _,
+            num_query_tcp,
+            num_query_tcp_out,
+            num_query_tls,
+            num_query_tls_resume,
+            num_query_ipv6,
+            num_query_edns_present,
+            num_query_edns_do,
+            num_query_rate_limited,
+            num_query_https,
+            num_answer_secure,
+            num_answer_bogus,
+            num_rrset_bogus,
+            num_unwanted_queries,
+            num_unwanted_replies,
+            num_query_dnscrypt_shared_secret_cache_miss,
+            num_query_dnscrypt_replay,
+            num_query_authzone_up,
+            num_query_authzone_down,
+            num_query_subnet,
+            num_query_subnet_cache,
+            num_query_cachedb,
+            unknown: _,
+            diagnostics: _,
+        } = other;
+
+        let counters = Statistics {
+            total: thread_counters(total),
+            threads: threads.iter().map(thread_counters).collect(),
+            time: Time::default(),
+            cache: Cache::default(),
+            modules: Modules::default(),
+            cache_count: CacheCounter::default(),
+            http: Http::default(),
+            flags: flags.clone(),
+            query_opcodes: query_opcodes.clone(),
+            query_types: query_types.clone(),
+            query_types_other: *query_types_other,
+            query_classes: query_classes.clone(),
+            query_classes_other: *query_classes_other,
+            answer_rcodes: answer_rcodes.clone(),
+            query_aggressive: query_aggressive.clone(),
+            rpz_actions: rpz_actions.clone(),
+            answer_ede: answer_ede.clone(),
+            histogram: histogram.clone(),
+            mem_streamwait: 0,
+            num_query_tcp: *num_query_tcp,
+            num_query_tcp_out: *num_query_tcp_out,
+            num_query_tls: *num_query_tls,
+            num_query_tls_resume: *num_query_tls_resume,
+            num_query_ipv6: *num_query_ipv6,
+            num_query_edns_present: *num_query_edns_present,
+            num_query_edns_do: *num_query_edns_do,
+            num_query_rate_limited: *num_query_rate_limited,
+            num_query_https: *num_query_https,
+            num_answer_secure: *num_answer_secure,
+            num_answer_bogus: *num_answer_bogus,
+            num_rrset_bogus: *num_rrset_bogus,
+            num_unwanted_queries: *num_unwanted_queries,
+            num_unwanted_replies: *num_unwanted_replies,
+            num_query_dnscrypt_shared_secret_cache_miss: *num_query_dnscrypt_shared_secret_cache_miss,
+            num_query_dnscrypt_replay: *num_query_dnscrypt_replay,
+            num_query_authzone_up: *num_query_authzone_up,
+            num_query_authzone_down: *num_query_authzone_down,
+            num_query_subnet: *num_query_subnet,
+            num_query_subnet_cache: *num_query_subnet_cache,
+            num_query_cachedb: *num_query_cachedb,
+            unknown: Vec::new(),
+            diagnostics: Vec::new(),
+        };
+
+        self.merge(&counters);
+    }
+}
+
+impl Sum for Statistics {
+    fn sum<I: Iterator<Item = Statistics>>(iter: I) -> Self {
+        iter.fold(Statistics::default(), |mut acc, statistics| {
+            acc.merge(&statistics);
+            acc
+        })
+    }
+}
+
+impl<'a> Sum<&'a Statistics> for Statistics {
+    fn sum<I: Iterator<Item = &'a Statistics>>(iter: I) -> Self {
+        iter.fold(Statistics::default(), |mut acc, statistics| {
+            acc.merge(statistics);
+            acc
+        })
+    }
+}
+
+// Counters are wrapping same as `Histogram::count` does,
+// so the overflow looks like a counter reset for the consumers.
+fn add(value: &mut u64, other: u64) {
+    *value = value.wrapping_add(other);
+}
+
+/// Average of two averages, weighted by the number of values each one was calculated for.
+fn weighted(value: f64, weight: u64, other: f64, other_weight: u64) -> f64 {
+    match (weight, other_weight) {
+        // Nothing to weight by, `max` keeps the merge with defaults unchanged
+        (0, 0) => value.max(other),
+        (0, _) => other,
+        (_, 0) => value,
+        _ => (value * weight as f64 + other * other_weight as f64) / (weight as f64 + other_weight as f64),
+    }
+}
+
+fn merge_map<K>(map: &mut HashMap<K, u64>, other: &HashMap<K, u64>)
+where
+    K: Eq + Hash + Clone,
+{
+    for (key, value) in other {
+        add(map.entry(key.clone()).or_default(), *value);
+    }
+}
+
+/// Copy of the `thread` without the current request list sizes and TCP buffers usage.
+fn thread_counters(thread: &Thread) -> Thread {
+    let Thread {
+        num_queries,
+        num_queries_ip_ratelimited,
+        num_cache_hits,
+        num_cache_miss,
+        num_prefetch,
+        num_zero_ttl,
+        num_recursive_replies,
+        num_dnscrypt_crypted,
+        num_dnscrypt_cert,
+        num_dnscrypt_cleartext,
+        num_dnscrypt_malformed,
+        requestlist_avg,
+        requestlist_max,
+        requestlist_overwritten,
+        requestlist_exceeded,
+        requestlist_current_all: _,
+        requestlist_current_user: _,
+        recursion_time_avg,
+        recursion_time_median,
+        tcp_usage: _,
+        answer_rcode,
+    } = thread;
+
+    Thread {
+        num_queries: *num_queries,
+        num_queries_ip_ratelimited: *num_queries_ip_ratelimited,
+        num_cache_hits: *num_cache_hits,
+        num_cache_miss: *num_cache_miss,
+        num_prefetch: *num_prefetch,
+        num_zero_ttl: *num_zero_ttl,
+        num_recursive_replies: *num_recursive_replies,
+        num_dnscrypt_crypted: *num_dnscrypt_crypted,
+        num_dnscrypt_cert: *num_dnscrypt_cert,
+        num_dnscrypt_cleartext: *num_dnscrypt_cleartext,
+        num_dnscrypt_malformed: *num_dnscrypt_malformed,
+        requestlist_avg: *requestlist_avg,
+        requestlist_max: *requestlist_max,
+        requestlist_overwritten: *requestlist_overwritten,
+        requestlist_exceeded: *requestlist_exceeded,
+        requestlist_current_all: 0,
+        requestlist_current_user: 0,
+        recursion_time_avg: *recursion_time_avg,
+        recursion_time_median: *recursion_time_median,
+        tcp_usage: 0,
+        answer_rcode: answer_rcode.clone(),
+    }
+}
+
+fn merge_thread(thread: &mut Thread, other: &Thread) {
+    let Thread {
+        num_queries,
+        num_queries_ip_ratelimited,
+        num_cache_hits,
+        num_cache_miss,
+        num_prefetch,
+        num_zero_ttl,
+        num_recursive_replies,
+        num_dnscrypt_crypted,
+        num_dnscrypt_cert,
+        num_dnscrypt_cleartext,
+        num_dnscrypt_malformed,
+        requestlist_avg,
+        requestlist_max,
+        requestlist_overwritten,
+        requestlist_exceeded,
+        requestlist_current_all,
+        requestlist_current_user,
+        recursion_time_avg,
+        recursion_time_median,
+        tcp_usage,
+        answer_rcode,
+    } = other;
+
+    // Averages should be calculated before the counters they are weighted by are summed.
+    // `unbound` samples the request list size each time a query enters it,
+    // which happens for cache misses and prefetches.
+    thread.requestlist_avg = weighted(
+        thread.requestlist_avg,
+        thread.num_cache_miss.wrapping_add(thread.num_prefetch),
+        *requestlist_avg,
+        num_cache_miss.wrapping_add(*num_prefetch),
+    );
+    thread.recursion_time_avg = weighted(
+        thread.recursion_time_avg,
+        thread.num_recursive_replies,
+        *recursion_time_avg,
+        *num_recursive_replies,
+    );
+    thread.recursion_time_median = weighted(
+        thread.recursion_time_median,
+        thread.num_recursive_replies,
+        *recursion_time_median,
+        *num_recursive_replies,
+    );
+
+    add(&mut thread.num_queries, *num_queries);
+    add(&mut thread.num_queries_ip_ratelimited, *num_queries_ip_ratelimited);
+    add(&mut thread.num_cache_hits, *num_cache_hits);
+    add(&mut thread.num_cache_miss, *num_cache_miss);
+    add(&mut thread.num_prefetch, *num_prefetch);
+    add(&mut thread.num_zero_ttl, *num_zero_ttl);
+    add(&mut thread.num_recursive_replies, *num_recursive_replies);
+    add(&mut thread.num_dnscrypt_crypted, *num_dnscrypt_crypted);
+    add(&mut thread.num_dnscrypt_cert, *num_dnscrypt_cert);
+    add(&mut thread.num_dnscrypt_cleartext, *num_dnscrypt_cleartext);
+    add(&mut thread.num_dnscrypt_malformed, *num_dnscrypt_malformed);
+    thread.requestlist_max = thread.requestlist_max.max(*requestlist_max);
+    add(&mut thread.requestlist_overwritten, *requestlist_overwritten);
+    add(&mut thread.requestlist_exceeded, *requestlist_exceeded);
+    add(&mut thread.requestlist_current_all, *requestlist_current_all);
+    add(&mut thread.requestlist_current_user, *requestlist_current_user);
+    add(&mut thread.tcp_usage, *tcp_usage);
+    merge_map(&mut thread.answer_rcode, answer_rcode);
+}
+
+fn merge_time(time: &mut Time, other: &Time) {
+    let Time { now, up, elapsed } = other;
+
+    time.now = time.now.max(*now);
+    time.up = time.up.max(*up);
+    time.elapsed = time.elapsed.max(*elapsed);
+}
+
+fn merge_cache(cache: &mut Cache, other: &Cache) {
+    let Cache {
+        rrset,
+        message,
+        dnscrypt_shared_secret,
+        dnscrypt_nonce,
+    } = other;
+
+    add(&mut cache.rrset, *rrset);
+    add(&mut cache.message, *message);
+    add(&mut cache.dnscrypt_shared_secret, *dnscrypt_shared_secret);
+    add(&mut cache.dnscrypt_nonce, *dnscrypt_nonce);
+}
+
+fn merge_modules(modules: &mut Modules, other: &Modules) {
+    let Modules {
+        iterator,
+        validator,
+        respip,
+        subnet,
+        ipsecmod,
+        dynlibmod,
+        cachedb,
+    } = other;
+
+    add(&mut modules.iterator, *iterator);
+    add(&mut modules.validator, *validator);
+    add(&mut modules.respip, *respip);
+    add(&mut modules.subnet, *subnet);
+    add(&mut modules.ipsecmod, *ipsecmod);
+    add(&mut modules.dynlibmod, *dynlibmod);
+    add(&mut modules.cachedb, *cachedb);
+}
+
+fn merge_cache_count(cache_count: &mut CacheCounter, other: &CacheCounter) {
+    let CacheCounter {
+        message,
+        rrset,
+        infra,
+        key,
+        dnscrypt_shared_secret,
+        dnscrypt_nonce,
+    } = other;
+
+    add(&mut cache_count.message, *message);
+    add(&mut cache_count.rrset, *rrset);
+    add(&mut cache_count.infra, *infra);
+    add(&mut cache_count.key, *key);
+    add(&mut cache_count.dnscrypt_shared_secret, *dnscrypt_shared_secret);
+    add(&mut cache_count.dnscrypt_nonce, *dnscrypt_nonce);
+}
+
+fn merge_http(http: &mut Http, other: &Http) {
+    let Http {
+        query_buffer,
+        response_buffer,
+    } = other;
+
+    add(&mut http.query_buffer, *query_buffer);
+    add(&mut http.response_buffer, *response_buffer);
+}
+
+fn merge_flags(flags: &mut Flags, other: &Flags) {
+    let Flags {
+        qr,
+        aa,
+        tc,
+        rd,
+        ra,
+        z,
+        ad,
+        cd,
+    } = other;
+
+    add(&mut flags.qr, *qr);
+    add(&mut flags.aa, *aa);
+    add(&mut flags.tc, *tc);
+    add(&mut flags.rd, *rd);
+    add(&mut flags.ra, *ra);
+    add(&mut flags.z, *z);
+    add(&mut flags.ad, *ad);
+    add(&mut flags.cd, *cd);
+}
+
+/// Buckets are matched by their upper bounds, buckets missing in `histogram` are appended to it.
+fn merge_histogram(histogram: &mut Histogram, other: &Histogram) {
+    let average = weighted(histogram.average(), histogram.count(), other.average(), other.count());
+
+    let mut merged = Histogram::default();
+    for (le, count) in histogram.raw_buckets() {
+        let other = other
+            .raw_buckets()
+            .find(|(other_le, _)| *other_le == le)
+            .map_or(0, |(_, count)| count);
+        merged.push(le, count.wrapping_add(other));
+    }
+    for (le, count) in other.raw_buckets() {
+        if !histogram.raw_buckets().any(|(known_le, _)| known_le == le) {
+            merged.push(le, count);
+        }
+    }
+    *merged.average_mut() = average;
+
+    *histogram = merged;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+    use std::time::Duration;
+
+    use claim::assert_ok;
+    use domain::base::iana::{Rcode, Rtype};
+
+    use crate::statistics::{Histogram, Statistics};
+
+    static STATS: &str = include_str!("../../assets/test_text_stats.txt");
+
+    #[test]
+    fn test_merge() {
+        let first = assert_ok!(Statistics::from_str(STATS));
+        let mut second = first.clone();
+        second.time.up += Duration::from_secs(10);
+        second.total.requestlist_max = 100;
+        second.cache.rrset = 10;
+        let _ = second.query_types.insert(Rtype::from_int(257), 5);
+
+        let mut merged = first.clone();
+        merged.merge(&second);
+
+        assert_eq!(merged.total.num_queries, first.total.num_queries * 2);
+        assert_eq!(merged.threads.len(), first.threads.len() * 2);
+        assert_eq!(merged.threads[first.threads.len()], second.threads[0]);
+        assert_eq!(merged.time.up, second.time.up);
+        assert_eq!(merged.total.requestlist_max, 100);
+        assert_eq!(merged.cache.rrset, first.cache.rrset + 10);
+        assert_eq!(
+            merged.answer_rcodes.get(&Rcode::NoError),
+            Some(&(first.answer_rcodes[&Rcode::NoError] * 2))
+        );
+        assert_eq!(
+            merged.query_types.get(&Rtype::A),
+            Some(&(first.query_types[&Rtype::A] * 2))
+        );
+        assert_eq!(merged.query_types.get(&Rtype::from_int(257)), Some(&5));
+        assert_eq!(merged.flags.rd, first.flags.rd * 2);
+
+        // Same averages are staying the same
+        approx::assert_relative_eq!(merged.total.recursion_time_avg, first.total.recursion_time_avg);
+        assert_eq!(merged.histogram.count(), first.histogram.count() * 2);
+        approx::assert_relative_eq!(merged.histogram.sum(), first.histogram.sum() * 2.0, max_relative = 1e-9);
+    }
+
+    #[test]
+    fn test_merge_counters() {
+        let first = assert_ok!(Statistics::from_str(STATS));
+        let mut stale = first.clone();
+        stale.time.up += Duration::from_secs(10);
+        stale.total.tcp_usage = 10;
+
+        let mut merged = first.clone();
+        merged.merge_counters(&stale);
+
+        assert_eq!(merged.total.num_queries, first.total.num_queries * 2);
+        assert_eq!(merged.threads.len(), first.threads.len() * 2);
+        assert_eq!(
+            merged.threads[first.threads.len()].num_queries,
+            stale.threads[0].num_queries
+        );
+        assert_eq!(merged.threads[first.threads.len()].requestlist_current_all, 0);
+        assert_eq!(merged.total.tcp_usage, first.total.tcp_usage);
+        assert_eq!(
+            merged.total.requestlist_current_all,
+            first.total.requestlist_current_all
+        );
+        assert_eq!(merged.time, first.time);
+        assert_eq!(merged.cache, first.cache);
+        assert_eq!(merged.mem_streamwait, first.mem_streamwait);
+        assert_eq!(merged.histogram.count(), first.histogram.count() * 2);
+        approx::assert_relative_eq!(merged.total.recursion_time_avg, first.total.recursion_time_avg);
+    }
+
+    #[test]
+    fn test_merge_weighted() {
+        let mut first = Statistics::default();
+        first.total.num_recursive_replies = 30;
+        first.total.recursion_time_avg = 0.1;
+        first.histogram.push(Duration::from_micros(131_072), 30);
+        *first.histogram.average_mut() = 0.1;
+
+        let mut second = Statistics::default();
+        second.total.num_recursive_replies = 10;
+        second.total.recursion_time_avg = 0.5;
+        second.histogram.push(Duration::from_micros(524_288), 10);
+        *second.histogram.average_mut() = 0.5;
+
+        first.merge(&second);
+        approx::assert_relative_eq!(first.total.recursion_time_avg, 0.2);
+        approx::assert_relative_eq!(first.histogram.average(), 0.2);
+        assert_eq!(
+            first.histogram.raw_buckets().collect::<Vec<_>>(),
+            vec![
+                (Duration::from_micros(131_072), 30),
+                (Duration::from_micros(524_288), 10)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sum() {
+        let stats = assert_ok!(Statistics::from_str(STATS));
+
+        // Default statistics is an identity element for merge
+        let single: Statistics = vec![stats.clone()].into_iter().sum();
+        assert_eq!(single, stats);
+        let empty: Statistics = Vec::<Statistics>::new().iter().sum();
+        assert_eq!(empty.threads.len(), 0);
+        assert_eq!(empty.histogram, Histogram::default());
+
+        let pool = [stats.clone(), stats.clone(), stats.clone()];
+        let merged: Statistics = pool.iter().sum();
+        assert_eq!(merged.total.num_queries, stats.total.num_queries * 3);
+        assert_eq!(merged.threads.len(), stats.threads.len() * 3);
+        assert_eq!(merged.unknown, stats.unknown);
+    }
+}
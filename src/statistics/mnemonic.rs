@@ -0,0 +1,160 @@
+//! `serde` helpers for the maps keyed by the DNS parameters.
+//!
+//! `domain` crate enums are not implementing `Serialize` and `Deserialize`,
+//! so the map keys are represented by the same mnemonics `unbound` uses (ex. `"AAAA"` or `"NXDOMAIN"`),
+//! see [`iana`](super::iana) module for details.
+
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+use std::str::FromStr;
+
+use domain::base::iana::{Class, Opcode, Rcode, Rtype};
+use serde_crate::de::Error;
+use serde_crate::{Deserialize, Deserializer, Serializer};
+
+use super::iana;
+use super::RpzAction;
+
+pub(crate) trait Mnemonic: Sized + Eq + Hash {
+    fn to_mnemonic(&self) -> Cow<'static, str>;
+
+    fn from_mnemonic(s: &str) -> Option<Self>;
+}
+
+impl Mnemonic for Rtype {
+    fn to_mnemonic(&self) -> Cow<'static, str> {
+        iana::rtype_to_str(*self)
+    }
+
+    fn from_mnemonic(s: &str) -> Option<Self> {
+        iana::parse_rtype(s)
+    }
+}
+
+impl Mnemonic for Class {
+    fn to_mnemonic(&self) -> Cow<'static, str> {
+        iana::class_to_str(*self)
+    }
+
+    fn from_mnemonic(s: &str) -> Option<Self> {
+        iana::parse_class(s)
+    }
+}
+
+impl Mnemonic for Opcode {
+    fn to_mnemonic(&self) -> Cow<'static, str> {
+        iana::opcode_to_str(*self)
+    }
+
+    fn from_mnemonic(s: &str) -> Option<Self> {
+        iana::parse_opcode(s)
+    }
+}
+
+impl Mnemonic for Rcode {
+    fn to_mnemonic(&self) -> Cow<'static, str> {
+        iana::rcode_to_str(*self)
+    }
+
+    fn from_mnemonic(s: &str) -> Option<Self> {
+        iana::parse_rcode(s)
+    }
+}
+
+impl Mnemonic for RpzAction {
+    fn to_mnemonic(&self) -> Cow<'static, str> {
+        Cow::Borrowed(self.as_str())
+    }
+
+    fn from_mnemonic(s: &str) -> Option<Self> {
+        RpzAction::from_str(s).ok()
+    }
+}
+
+/// Used as `#[serde(with = "mnemonic::map")]` for the `HashMap<K, u64>` fields.
+pub(crate) mod map {
+    use super::*;
+
+    pub fn serialize<K, S>(map: &HashMap<K, u64>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        K: Mnemonic,
+        S: Serializer,
+    {
+        // Sorted in order to have the stable output
+        let sorted = map
+            .iter()
+            .map(|(key, value)| (key.to_mnemonic(), value))
+            .collect::<BTreeMap<_, _>>();
+
+        serializer.collect_map(sorted)
+    }
+
+    pub fn deserialize<'de, K, D>(deserializer: D) -> Result<HashMap<K, u64>, D::Error>
+    where
+        K: Mnemonic,
+        D: Deserializer<'de>,
+    {
+        HashMap::<String, u64>::deserialize(deserializer)?
+            .into_iter()
+            .map(|(key, value)| match K::from_mnemonic(&key) {
+                Some(key) => Ok((key, value)),
+                None => Err(D::Error::custom(format_args!("unknown mnemonic `{}`", key))),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use claim::{assert_err, assert_ok};
+    use domain::base::iana::{Rcode, Rtype};
+
+    use crate::statistics::Statistics;
+
+    static STATS: &str = include_str!("../../assets/test_text_stats.txt");
+
+    #[test]
+    fn test_json_round_trip() {
+        let stats = assert_ok!(Statistics::from_str(STATS));
+
+        let json = assert_ok!(serde_json::to_string(&stats));
+        let parsed: Statistics = assert_ok!(serde_json::from_str(&json));
+
+        assert_eq!(parsed, stats);
+    }
+
+    #[test]
+    fn test_json_field_names() {
+        let stats = assert_ok!(Statistics::from_str(STATS));
+        let json = assert_ok!(serde_json::to_value(&stats));
+
+        assert_eq!(json["total"]["num_queries"], 1338);
+        assert_eq!(json["threads"][1]["num_cache_hits"], 101);
+        assert_eq!(json["time"]["up"]["secs"], 32586);
+        assert_eq!(json["cache_count"]["infra"], 2);
+        assert_eq!(json["query_types"]["A"], 4_576_639_648u64);
+        assert_eq!(json["query_types"]["HTTPS"], 140_375_490);
+        assert_eq!(json["query_classes"]["CLASS5"], 1);
+        assert_eq!(json["answer_rcodes"]["NOTIMPL"], 0);
+        assert_eq!(json["histogram"]["buckets"][0]["count"], 22);
+    }
+
+    #[test]
+    fn test_json_partial() {
+        // Missing fields are defaulted, so the older dumps are still readable
+        let stats: Statistics = assert_ok!(serde_json::from_str(
+            r#"{"total": {"num_queries": 5}, "query_types": {"AAAA": 3}}"#
+        ));
+
+        assert_eq!(stats.total.num_queries, 5);
+        assert_eq!(stats.query_types.get(&Rtype::Aaaa), Some(&3));
+        assert_eq!(stats.answer_rcodes.get(&Rcode::NoError), None);
+
+        assert_err!(serde_json::from_str::<Statistics>(
+            r#"{"query_types": {"WHATEVER": 3}}"#
+        ));
+    }
+}
@@ -4,17 +4,31 @@ use std::time::Duration;
 
 use domain::base::iana::{Class, Opcode, Rcode, Rtype};
 
+mod delta;
 mod histogram;
+pub(crate) mod iana;
+mod merge;
+#[cfg(feature = "serde")]
+mod mnemonic;
 mod parser;
+mod rpz;
+mod text;
 
+pub use self::delta::{FlagsRates, StatisticsDelta, StatisticsRates, ThreadRates};
 pub use self::histogram::{Bucket, Histogram};
-pub use self::parser::ParseError;
+pub use self::parser::{ParseError, ParseErrorKind, ParseMode, Parser};
+pub use self::rpz::RpzAction;
 
 /// Statistics snapshot received from some data source.
 ///
 /// It is decoupled from any data layout or format exposed by `unbound`
 /// and mostly exists only to make sure that all keys are provided by all the data sources.
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_crate::Serialize, serde_crate::Deserialize),
+    serde(crate = "serde_crate", default)
+)]
 pub struct Statistics {
     pub total: Thread,
     pub threads: Vec<Thread>,
@@ -24,17 +38,26 @@ pub struct Statistics {
     pub cache_count: CacheCounter,
     pub http: Http,
     pub flags: Flags,
+    #[cfg_attr(feature = "serde", serde(with = "mnemonic::map"))]
     pub query_opcodes: HashMap<Opcode, u64>,
+    #[cfg_attr(feature = "serde", serde(with = "mnemonic::map"))]
     pub query_types: HashMap<Rtype, u64>,
     // All other `Rtype` entries higher than `UB_STATS_QTYPE_NUM` (declared in `unbound.h`)
     // are summed together into one metric value.
     // As they are not representing any specific `Rtype`, storing them separately in here too.
     pub query_types_other: u64,
+    #[cfg_attr(feature = "serde", serde(with = "mnemonic::map"))]
     pub query_classes: HashMap<Class, u64>,
     // See `query_types_other` comment for motivation to have this separate field.
     pub query_classes_other: u64,
+    #[cfg_attr(feature = "serde", serde(with = "mnemonic::map"))]
     pub answer_rcodes: HashMap<Rcode, u64>,
+    #[cfg_attr(feature = "serde", serde(with = "mnemonic::map"))]
     pub query_aggressive: HashMap<Rcode, u64>,
+    #[cfg_attr(feature = "serde", serde(with = "mnemonic::map"))]
+    pub rpz_actions: HashMap<RpzAction, u64>,
+    // Extended DNS Error (RFC 8914) info codes of the answers
+    pub answer_ede: HashMap<u16, u64>,
     pub histogram: Histogram,
     pub mem_streamwait: u64,
     pub num_query_tcp: u64,
@@ -57,6 +80,15 @@ pub struct Statistics {
     pub num_query_authzone_down: u64,
     pub num_query_subnet: u64,
     pub num_query_subnet_cache: u64,
+    pub num_query_cachedb: u64,
+    /// Keys (and their raw values) which are not known to the parser.
+    ///
+    /// Newer `unbound` versions might add keys faster than this crate is updated,
+    /// so they are collected in here instead of failing the whole parsing.
+    pub unknown: Vec<(String, String)>,
+    /// Malformed lines which were skipped by the parser in the `ParseMode::Lenient` mode.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub diagnostics: Vec<ParseError>,
 }
 
 impl str::FromStr for Statistics {
@@ -69,7 +101,12 @@ impl str::FromStr for Statistics {
 }
 
 /// Thread related data.
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_crate::Serialize, serde_crate::Deserialize),
+    serde(crate = "serde_crate", default)
+)]
 pub struct Thread {
     // Num
     pub num_queries: u64,
@@ -92,17 +129,28 @@ pub struct Thread {
     pub recursion_time_avg: f64,
     pub recursion_time_median: f64,
     pub tcp_usage: u64,
+    #[cfg_attr(feature = "serde", serde(with = "mnemonic::map"))]
     pub answer_rcode: HashMap<Rcode, u64>,
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_crate::Serialize, serde_crate::Deserialize),
+    serde(crate = "serde_crate", default)
+)]
 pub struct Time {
     pub now: Duration,
     pub up: Duration,
     pub elapsed: Duration,
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_crate::Serialize, serde_crate::Deserialize),
+    serde(crate = "serde_crate", default)
+)]
 pub struct Cache {
     pub rrset: u64,
     pub message: u64,
@@ -110,15 +158,28 @@ pub struct Cache {
     pub dnscrypt_nonce: u64,
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_crate::Serialize, serde_crate::Deserialize),
+    serde(crate = "serde_crate", default)
+)]
 pub struct Modules {
     pub iterator: u64,
     pub validator: u64,
     pub respip: u64,
     pub subnet: u64,
+    pub ipsecmod: u64,
+    pub dynlibmod: u64,
+    pub cachedb: u64,
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_crate::Serialize, serde_crate::Deserialize),
+    serde(crate = "serde_crate", default)
+)]
 pub struct CacheCounter {
     pub message: u64,
     pub rrset: u64,
@@ -128,7 +189,12 @@ pub struct CacheCounter {
     pub dnscrypt_nonce: u64,
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_crate::Serialize, serde_crate::Deserialize),
+    serde(crate = "serde_crate", default)
+)]
 pub struct Flags {
     pub qr: u64,
     pub aa: u64,
@@ -140,7 +206,12 @@ pub struct Flags {
     pub cd: u64,
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_crate::Serialize, serde_crate::Deserialize),
+    serde(crate = "serde_crate", default)
+)]
 pub struct Http {
     pub query_buffer: u64,
     pub response_buffer: u64,
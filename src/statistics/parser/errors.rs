@@ -2,55 +2,108 @@ use std::error;
 use std::fmt;
 use std::num;
 
-#[derive(Debug)]
-pub enum ParseError {
-    UnknownKey {
-        key: String,
-    },
+/// Kind of the [`ParseError`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseErrorKind {
+    UnknownKey,
     MissingKey,
-    MissingValue {
-        key: String,
-    },
+    MissingValue,
     ParseInt(num::ParseIntError),
     ParseFloat(num::ParseFloatError),
     ParseStr(String),
+    /// Statistics text representation is larger than allowed by `Parser::max_size`.
+    TooLarge {
+        limit: usize,
+    },
 
     /// Generic error kind if none of other variants can suit to the case.
     InvalidFormat,
 }
 
-impl fmt::Display for ParseError {
+impl fmt::Display for ParseErrorKind {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            ParseError::UnknownKey { key } => f.write_fmt(format_args!("Unknown key {}", key)),
-            ParseError::MissingKey => f.write_str("Text line is missing a key definition"),
-            ParseError::MissingValue { key } => f.write_fmt(format_args!("Missing value for key {}", key)),
-            ParseError::ParseInt(e) => fmt::Display::fmt(e, f),
-            ParseError::ParseFloat(e) => fmt::Display::fmt(e, f),
-            ParseError::ParseStr(value) => f.write_fmt(format_args!("Unable to parse '{}' value", value)),
-            ParseError::InvalidFormat => f.write_str("Invalid data format"),
+            ParseErrorKind::UnknownKey => f.write_str("Unknown key"),
+            ParseErrorKind::MissingKey => f.write_str("Text line is missing a key definition"),
+            ParseErrorKind::MissingValue => f.write_str("Missing value"),
+            ParseErrorKind::ParseInt(e) => fmt::Display::fmt(e, f),
+            ParseErrorKind::ParseFloat(e) => fmt::Display::fmt(e, f),
+            ParseErrorKind::ParseStr(value) => f.write_fmt(format_args!("Unable to parse '{}' value", value)),
+            ParseErrorKind::TooLarge { limit } => {
+                f.write_fmt(format_args!("Statistics data exceeds the limit of {} bytes", limit))
+            }
+            ParseErrorKind::InvalidFormat => f.write_str("Invalid data format"),
         }
     }
 }
 
-impl error::Error for ParseError {
-    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
-        match self {
-            ParseError::ParseInt(e) => Some(e),
-            ParseError::ParseFloat(e) => Some(e),
-            _ => None,
+impl From<num::ParseFloatError> for ParseErrorKind {
+    fn from(e: num::ParseFloatError) -> Self {
+        ParseErrorKind::ParseFloat(e)
+    }
+}
+
+impl From<num::ParseIntError> for ParseErrorKind {
+    fn from(e: num::ParseIntError) -> Self {
+        ParseErrorKind::ParseInt(e)
+    }
+}
+
+/// Error which happened during the statistics parsing,
+/// along with the line where it happened.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    kind: ParseErrorKind,
+    line: usize,
+    key: String,
+    value: String,
+}
+
+impl ParseError {
+    pub fn new(kind: ParseErrorKind, line: usize, key: impl Into<String>, value: impl Into<String>) -> Self {
+        Self {
+            kind,
+            line,
+            key: key.into(),
+            value: value.into(),
         }
     }
+
+    pub fn kind(&self) -> &ParseErrorKind {
+        &self.kind
+    }
+
+    /// Line number (starting from `1`) where error happened.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// Full key of the line, might be empty if the line is missing it.
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Raw value of the line, might be empty if the line is missing it.
+    pub fn value(&self) -> &str {
+        &self.value
+    }
 }
 
-impl From<num::ParseFloatError> for ParseError {
-    fn from(e: num::ParseFloatError) -> Self {
-        ParseError::ParseFloat(e)
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_fmt(format_args!(
+            "{} at line {} (key '{}', value '{}')",
+            self.kind, self.line, self.key, self.value
+        ))
     }
 }
 
-impl From<num::ParseIntError> for ParseError {
-    fn from(e: num::ParseIntError) -> Self {
-        ParseError::ParseInt(e)
+impl error::Error for ParseError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match &self.kind {
+            ParseErrorKind::ParseInt(e) => Some(e),
+            ParseErrorKind::ParseFloat(e) => Some(e),
+            _ => None,
+        }
     }
 }
@@ -0,0 +1,137 @@
+//! Static dispatch tables for the plain (non-parametrized) statistics keys.
+//!
+//! Both tables are sorted by the key length first and by the key itself next,
+//! so the lookup is a binary search, which compares the lengths for the most of the probes
+//! and goes to the bytes comparison only for the keys of the same length.
+//! `test_dispatch_tables_sorted` test will catch the misplaced entries.
+
+use std::cmp::Ordering;
+
+use super::types::Field;
+use super::ParseErrorKind;
+use crate::statistics::{Statistics, Thread};
+
+pub(crate) type Setter<T> = fn(&mut T, &str) -> Result<(), ParseErrorKind>;
+
+macro_rules! field {
+    ($($path:ident).+) => {
+        |target, value| target.$($path).+.parse(value)
+    };
+}
+
+/// Keys which are shared by the `threadN.` and `total.` sections.
+pub(crate) static THREAD: &[(&str, Setter<Thread>)] = &[
+    ("tcpusage", field!(tcp_usage)),
+    // Metric name after unbound version 1.10.1
+    // see https://github.com/NLnetLabs/unbound/commit/f7fe95ad7bae690781f9b78ca252a44fc072ca33
+    ("num.expired", field!(num_zero_ttl)),
+    ("num.queries", field!(num_queries)),
+    ("num.prefetch", field!(num_prefetch)),
+    // Metric name before unbound version 1.10.1
+    ("num.zero_ttl", field!(num_zero_ttl)),
+    ("num.cachehits", field!(num_cache_hits)),
+    ("num.cachemiss", field!(num_cache_miss)),
+    ("requestlist.avg", field!(requestlist_avg)),
+    ("requestlist.max", field!(requestlist_max)),
+    ("num.dnscrypt.cert", field!(num_dnscrypt_cert)),
+    ("recursion.time.avg", field!(recursion_time_avg)),
+    ("num.dnscrypt.crypted", field!(num_dnscrypt_crypted)),
+    ("num.recursivereplies", field!(num_recursive_replies)),
+    ("requestlist.exceeded", field!(requestlist_exceeded)),
+    ("recursion.time.median", field!(recursion_time_median)),
+    ("num.dnscrypt.cleartext", field!(num_dnscrypt_cleartext)),
+    ("num.dnscrypt.malformed", field!(num_dnscrypt_malformed)),
+    ("requestlist.current.all", field!(requestlist_current_all)),
+    ("requestlist.overwritten", field!(requestlist_overwritten)),
+    ("requestlist.current.user", field!(requestlist_current_user)),
+    ("num.queries_ip_ratelimited", field!(num_queries_ip_ratelimited)),
+];
+
+/// Global keys, which are not belonging to any thread.
+pub(crate) static OTHER: &[(&str, Setter<Statistics>)] = &[
+    ("time.up", field!(time.up)),
+    ("time.now", field!(time.now)),
+    ("time.elapsed", field!(time.elapsed)),
+    ("num.query.tcp", field!(num_query_tcp)),
+    ("num.query.tls", field!(num_query_tls)),
+    ("mem.mod.respip", field!(modules.respip)),
+    ("mem.mod.subnet", field!(modules.subnet)),
+    ("mem.streamwait", field!(mem_streamwait)),
+    ("num.query.ipv6", field!(num_query_ipv6)),
+    ("key.cache.count", field!(cache_count.key)),
+    ("mem.cache.rrset", field!(cache.rrset)),
+    ("mem.mod.cachedb", field!(modules.cachedb)),
+    ("msg.cache.count", field!(cache_count.message)),
+    ("num.query.https", field!(num_query_https)),
+    ("num.rrset.bogus", field!(num_rrset_bogus)),
+    ("mem.mod.ipsecmod", field!(modules.ipsecmod)),
+    ("mem.mod.iterator", field!(modules.iterator)),
+    ("num.answer.bogus", field!(num_answer_bogus)),
+    ("num.query.subnet", field!(num_query_subnet)),
+    ("num.query.tcpout", field!(num_query_tcp_out)),
+    ("unwanted.queries", field!(num_unwanted_queries)),
+    ("unwanted.replies", field!(num_unwanted_replies)),
+    ("infra.cache.count", field!(cache_count.infra)),
+    ("mem.cache.message", field!(cache.message)),
+    ("mem.mod.dynlibmod", field!(modules.dynlibmod)),
+    ("mem.mod.validator", field!(modules.validator)),
+    ("num.answer.secure", field!(num_answer_secure)),
+    ("num.query.cachedb", field!(num_query_cachedb)),
+    ("num.query.edns.DO", field!(num_query_edns_do)),
+    ("num.query.flags.Z", field!(flags.z)),
+    ("rrset.cache.count", field!(cache_count.rrset)),
+    ("num.query.flags.AA", field!(flags.aa)),
+    ("num.query.flags.AD", field!(flags.ad)),
+    ("num.query.flags.CD", field!(flags.cd)),
+    ("num.query.flags.QR", field!(flags.qr)),
+    ("num.query.flags.RA", field!(flags.ra)),
+    ("num.query.flags.RD", field!(flags.rd)),
+    ("num.query.flags.TC", field!(flags.tc)),
+    ("num.query.tls.resume", field!(num_query_tls_resume)),
+    ("num.query.type.other", field!(query_types_other)),
+    ("mem.http.query_buffer", field!(http.query_buffer)),
+    ("num.query.authzone.up", field!(num_query_authzone_up)),
+    ("num.query.class.other", field!(query_classes_other)),
+    ("num.query.ratelimited", field!(num_query_rate_limited)),
+    ("num.query.edns.present", field!(num_query_edns_present)),
+    ("num.query.subnet_cache", field!(num_query_subnet_cache)),
+    // `rcode.nodata` is ignored, same to `kumina/unbound_exporter`
+    ("num.answer.rcode.nodata", |_, _| Ok(())),
+    ("num.query.authzone.down", field!(num_query_authzone_down)),
+    ("mem.cache.dnscrypt_nonce", field!(cache.dnscrypt_nonce)),
+    ("mem.http.response_buffer", field!(http.response_buffer)),
+    ("num.query.dnscrypt.replay", field!(num_query_dnscrypt_replay)),
+    ("dnscrypt_nonce.cache.count", field!(cache_count.dnscrypt_nonce)),
+    ("mem.cache.dnscrypt_shared_secret", field!(cache.dnscrypt_shared_secret)),
+    (
+        "dnscrypt_shared_secret.cache.count",
+        field!(cache_count.dnscrypt_shared_secret),
+    ),
+    (
+        "num.query.dnscrypt.shared_secret.cachemiss",
+        field!(num_query_dnscrypt_shared_secret_cache_miss),
+    ),
+];
+
+/// Finds the setter for the `key` in one of the tables above.
+#[inline]
+pub(crate) fn lookup<T>(table: &'static [(&'static str, Setter<T>)], key: &str) -> Option<Setter<T>> {
+    table
+        .binary_search_by(|(probe, _)| compare(probe, key))
+        .ok()
+        .map(|idx| table[idx].1)
+}
+
+/// Order used by the tables: by length and then bytewise.
+///
+/// Comparing the lengths first rejects most of the probed keys
+/// without comparing their long common prefixes (ex. `num.query.`).
+#[inline]
+pub(crate) fn compare(left: &str, right: &str) -> Ordering {
+    left.len().cmp(&right.len()).then_with(|| {
+        left.bytes()
+            .zip(right.bytes())
+            .find(|(l, r)| l != r)
+            .map_or(Ordering::Equal, |(l, r)| l.cmp(&r))
+    })
+}
@@ -1,15 +1,43 @@
+use std::io;
+use std::marker::Unpin;
 use std::str::FromStr;
-use std::time::Duration;
-use std::u64;
+
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt};
 
 mod errors;
+mod keys;
 mod types;
 
-pub use self::errors::ParseError;
-use self::types::{parse_class, parse_rcode, parse_rtype, DurationExt, Field};
-use super::{Opcode, Statistics, Thread};
+pub use self::errors::{ParseError, ParseErrorKind};
+use self::types::parse_rcode;
+use super::iana::{parse_class, parse_opcode, parse_rtype};
+use super::{RpzAction, Statistics, Thread};
 use crate::statistics::Histogram;
 
+/// Defines how the [`Parser`] treats the unknown keys and malformed lines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseMode {
+    /// Unknown keys are collected into the [`Statistics::unknown`] list,
+    /// malformed lines are failing the parsing.
+    Tolerant,
+    /// Any unknown key or malformed line fails the parsing.
+    ///
+    /// Useful to check if the new `unbound` release introduced any keys
+    /// which are not supported yet.
+    Strict,
+    /// Unknown keys are collected into the [`Statistics::unknown`] list
+    /// and malformed lines are skipped and collected into the [`Statistics::diagnostics`] list.
+    ///
+    /// Useful when the connection to `unbound` is not reliable and response might be truncated.
+    Lenient,
+}
+
+impl Default for ParseMode {
+    fn default() -> Self {
+        ParseMode::Tolerant
+    }
+}
+
 /// Parser for [`Statistics`] from the string representation.
 ///
 /// This representation can be obtained from the Unix or TLS socket.
@@ -18,6 +46,11 @@ use crate::statistics::Histogram;
 #[derive(Debug)]
 pub struct Parser {
     stats: Statistics,
+    mode: ParseMode,
+    // Number of the lines fed so far
+    line: usize,
+    // Maximum size of the statistics data in bytes
+    max_size: Option<usize>,
 }
 
 impl Parser {
@@ -25,219 +58,201 @@ impl Parser {
         Parser::default()
     }
 
+    pub fn with_mode(mode: ParseMode) -> Parser {
+        Parser {
+            stats: Statistics::default(),
+            mode,
+            line: 0,
+            max_size: None,
+        }
+    }
+
+    /// Limit the size of the statistics data which this parser will accept.
+    pub fn max_size(mut self, bytes: usize) -> Parser {
+        self.max_size = Some(bytes);
+        self
+    }
+
     pub fn parse(mut self, s: &str) -> Result<Statistics, ParseError> {
+        if let Some(limit) = self.max_size {
+            if s.len() > limit {
+                return Err(ParseError::new(ParseErrorKind::TooLarge { limit }, 0, "", ""));
+            }
+        }
+
         for line in s.lines() {
-            let line = line.trim();
-            if line.is_empty() {
-                continue;
+            self.feed_line(line)?;
+        }
+
+        self.finish()
+    }
+
+    /// Parse the statistics from the `reader` line by line, as soon as data arrives.
+    ///
+    /// Unlike reading the whole data first and calling [`Parser::parse`] later,
+    /// memory usage is bound by the longest line and not by the whole data size.
+    ///
+    /// Parse errors are returned as `io::Error` with the `InvalidData` kind.
+    pub async fn parse_reader<R>(mut self, reader: R) -> io::Result<Statistics>
+    where
+        R: AsyncBufRead + Unpin,
+    {
+        // Reading one extra byte allows to distinguish data of exactly `max_size` bytes from the larger one
+        let limit = self.max_size.map_or(u64::MAX, |bytes| (bytes as u64).saturating_add(1));
+        let mut reader = reader.take(limit);
+        let mut buffer = Vec::with_capacity(128);
+        let mut total = 0;
+
+        loop {
+            buffer.clear();
+            let read = reader.read_until(b'\n', &mut buffer).await?;
+            if read == 0 {
+                break;
             }
 
-            match self.feed_line(line) {
-                Ok(..) => {}
-                Err(ParseError::UnknownKey { .. }) => {
-                    log::warn!("Unable to parse '{}', unknown key", line);
+            total += read;
+            if let Some(limit) = self.max_size {
+                if total > limit {
+                    let e = ParseError::new(ParseErrorKind::TooLarge { limit }, self.line + 1, "", "");
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, e));
                 }
-                Err(e) => return Err(e),
             }
-        }
 
-        *self.stats.histogram.average_mut() = self.stats.total.recursion_time_avg;
+            // Invalid UTF-8 sequences are handled by the line parsing
+            // the same way as any other malformed data.
+            let line = String::from_utf8_lossy(&buffer);
+            self.feed_line(&line)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        }
 
-        self.finish()
+        self.finish().map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
     }
 
     // For now assuming that all data was provided correctly
-    pub fn finish(self) -> Result<Statistics, ParseError> {
+    pub fn finish(mut self) -> Result<Statistics, ParseError> {
+        *self.stats.histogram.average_mut() = self.stats.total.recursion_time_avg;
+
         Ok(self.stats)
     }
 
+    /// Feed the next line of the statistics text representation to the parser.
+    ///
+    /// Lines should be fed in order without any omissions (including the empty ones),
+    /// so errors would point to the correct line numbers.
     pub fn feed_line(&mut self, line: &str) -> Result<(), ParseError> {
-        let mut parts = line.splitn(2, '=');
-        let key = parts.next().ok_or(ParseError::MissingKey)?;
-        let value = parts
-            .next()
-            .ok_or_else(|| ParseError::MissingValue { key: key.into() })?;
-        let mut key_parts = key.splitn(2, '.');
-        let key_prefix = key_parts.next().ok_or(ParseError::InvalidFormat)?;
-        let key_postfix = key_parts.next().ok_or(ParseError::InvalidFormat)?;
+        self.line += 1;
+
+        let line = line.trim();
+        if line.is_empty() {
+            return Ok(());
+        }
+
+        let (key, value) = match split_once(line, b'=') {
+            Some((key, value)) => (key, Some(value)),
+            None => (line, None),
+        };
+
+        let result = match value {
+            _ if key.is_empty() => Err(ParseErrorKind::MissingKey),
+            Some(value) => self.feed(key, value),
+            None => Err(ParseErrorKind::MissingValue),
+        };
+        let value = value.unwrap_or_default();
+
+        match (result, self.mode) {
+            (Ok(()), _) => Ok(()),
+            (Err(ParseErrorKind::UnknownKey), mode) if mode != ParseMode::Strict => {
+                log::debug!("Unable to parse '{}', unknown key", line);
+                self.stats.unknown.push((key.to_owned(), value.to_owned()));
+                Ok(())
+            }
+            (Err(kind), ParseMode::Lenient) => {
+                let e = ParseError::new(kind, self.line, key, value);
+                log::debug!("Skipping malformed line: {}", e);
+                self.stats.diagnostics.push(e);
+                Ok(())
+            }
+            (Err(kind), _) => Err(ParseError::new(kind, self.line, key, value)),
+        }
+    }
+
+    fn feed(&mut self, key: &str, value: &str) -> Result<(), ParseErrorKind> {
+        let (key_prefix, key_postfix) = split_once(key, b'.').ok_or(ParseErrorKind::InvalidFormat)?;
 
         match key_prefix {
-            "total" => Self::thread(&mut self.stats.total, key_postfix, value)?,
+            "total" => Self::thread(&mut self.stats.total, key_postfix, value),
+            "histogram" => Self::histogram(&mut self.stats.histogram, key_postfix, value),
             prefix if prefix.starts_with("thread") => {
-                // TODO: Unnecessary allocation
-                let thread_id = prefix
-                    .chars()
-                    .skip("thread".len())
-                    .collect::<String>()
-                    .parse::<usize>()?;
+                let thread_id = prefix["thread".len()..].parse::<usize>()?;
 
                 if cfg!(fuzzing) && thread_id > 255 {
-                    return Err(ParseError::InvalidFormat);
+                    return Err(ParseErrorKind::InvalidFormat);
                 }
 
-                self.stats.threads.resize_with(thread_id + 1, Default::default);
-                let mut thread = self
-                    .stats
-                    .threads
-                    .get_mut(thread_id)
-                    .expect("Can't happen, resize_with will handle that");
+                // Threads list is only growing, so the out of order thread sections
+                // are not dropping the already parsed threads.
+                if thread_id >= self.stats.threads.len() {
+                    self.stats.threads.resize_with(thread_id + 1, Default::default);
+                }
 
-                Self::thread(&mut thread, key_postfix, value)?
+                Self::thread(&mut self.stats.threads[thread_id], key_postfix, value)
             }
-            "histogram" => Self::histogram(&mut self.stats.histogram, key_postfix, value)?,
-            _ => Self::other(&mut self.stats, key, value)?,
+            _ => Self::other(&mut self.stats, key, value),
         }
-
-        Ok(())
     }
 
-    fn thread(thread: &mut Thread, key: &str, value: &str) -> Result<(), ParseError> {
-        match key {
-            "num.queries" => thread.num_queries.parse(value),
-            "num.queries_ip_ratelimited" => thread.num_queries_ip_ratelimited.parse(value),
-            "num.cachehits" => thread.num_cache_hits.parse(value),
-            "num.cachemiss" => thread.num_cache_miss.parse(value),
-            "num.prefetch" => thread.num_prefetch.parse(value),
-
-            // Metric name before unbound version 1.10.1
-            "num.zero_ttl" => thread.num_zero_ttl.parse(value),
-            // Metric name after unbound version 1.10.1
-            // see https://github.com/NLnetLabs/unbound/commit/f7fe95ad7bae690781f9b78ca252a44fc072ca33
-            "num.expired" => thread.num_zero_ttl.parse(value),
-
-            "num.recursivereplies" => thread.num_recursive_replies.parse(value),
-            "num.dnscrypt.crypted" => thread.num_dnscrypt_crypted.parse(value),
-            "num.dnscrypt.cert" => thread.num_dnscrypt_cert.parse(value),
-            "num.dnscrypt.cleartext" => thread.num_dnscrypt_cleartext.parse(value),
-            "num.dnscrypt.malformed" => thread.num_dnscrypt_malformed.parse(value),
-            "requestlist.avg" => thread.requestlist_avg.parse(value),
-            "requestlist.max" => thread.requestlist_max.parse(value),
-            "requestlist.overwritten" => thread.requestlist_overwritten.parse(value),
-            "requestlist.exceeded" => thread.requestlist_exceeded.parse(value),
-            "requestlist.current.all" => thread.requestlist_current_all.parse(value),
-            "requestlist.current.user" => thread.requestlist_current_user.parse(value),
-            "recursion.time.avg" => thread.recursion_time_avg.parse(value),
-            "recursion.time.median" => thread.recursion_time_median.parse(value),
-            "tcpusage" => thread.tcp_usage.parse(value),
-            _ => Err(ParseError::UnknownKey { key: key.into() }),
+    fn thread(thread: &mut Thread, key: &str, value: &str) -> Result<(), ParseErrorKind> {
+        match keys::lookup(keys::THREAD, key) {
+            Some(setter) => setter(thread, value),
+            None => Err(ParseErrorKind::UnknownKey),
         }
     }
 
-    fn other(stats: &mut Statistics, key: &str, value: &str) -> Result<(), ParseError> {
-        match key {
-            "time.now" => stats.time.now.parse(value),
-            "time.up" => stats.time.up.parse(value),
-            "time.elapsed" => stats.time.elapsed.parse(value),
-            "mem.cache.rrset" => stats.cache.rrset.parse(value),
-            "mem.cache.message" => stats.cache.message.parse(value),
-            "mem.mod.iterator" => stats.modules.iterator.parse(value),
-            "mem.mod.validator" => stats.modules.validator.parse(value),
-            "mem.mod.respip" => stats.modules.respip.parse(value),
-            "mem.mod.subnet" => stats.modules.subnet.parse(value),
-            "mem.cache.dnscrypt_shared_secret" => stats.cache.dnscrypt_shared_secret.parse(value),
-            "mem.cache.dnscrypt_nonce" => stats.cache.dnscrypt_nonce.parse(value),
-            "mem.streamwait" => stats.mem_streamwait.parse(value),
-            "mem.http.query_buffer" => stats.http.query_buffer.parse(value),
-            "mem.http.response_buffer" => stats.http.response_buffer.parse(value),
-            "num.query.type.other" => stats.query_types_other.parse(value),
-            key if key.starts_with("num.query.type.") => {
-                let mut parts = key.rsplitn(2, '.');
-                let raw_type = parts.next().ok_or(ParseError::InvalidFormat)?;
-                // TODO: Unknown rtypes are ignored for now, see #101
-                if let Ok(type_) = parse_rtype(raw_type) {
-                    let value = value.parse::<u64>()?;
-                    let _ = stats.query_types.insert(type_, value);
-                }
-
-                Ok(())
-            }
-            "num.query.class.other" => stats.query_classes_other.parse(value),
-            key if key.starts_with("num.query.class.") => {
-                let mut parts = key.rsplitn(2, '.');
-                let raw_class = parts.next().ok_or(ParseError::InvalidFormat)?;
-                // TODO: Unknown classes are ignored for now, see #101
-                if let Ok(class) = parse_class(raw_class) {
-                    let value = value.parse::<u64>()?;
-                    let _ = stats.query_classes.insert(class, value);
-                }
+    fn other(stats: &mut Statistics, key: &str, value: &str) -> Result<(), ParseErrorKind> {
+        if let Some(setter) = keys::lookup(keys::OTHER, key) {
+            return setter(stats, value);
+        }
 
-                Ok(())
-            }
-            key if key.starts_with("num.query.opcode.") => {
-                let mut parts = key.rsplitn(2, '.');
-                let raw_code = parts.next().ok_or(ParseError::InvalidFormat)?;
-                // TODO: Unknown opcodes are ignored for now, see #101
-                let code = Opcode::from_str(raw_code).map_err(|_| ParseError::ParseStr(raw_code.into()));
-                if let Ok(code) = code {
-                    let value = value.parse::<u64>()?;
-                    let _ = stats.query_opcodes.insert(code, value);
-                }
-                Ok(())
-            }
-            "num.query.tcp" => stats.num_query_tcp.parse(value),
-            "num.query.tcpout" => stats.num_query_tcp_out.parse(value),
-            "num.query.tls" => stats.num_query_tls.parse(value),
-            "num.query.tls.resume" => stats.num_query_tls_resume.parse(value),
-            "num.query.ipv6" => stats.num_query_ipv6.parse(value),
-            "num.query.https" => stats.num_query_https.parse(value),
-            "num.query.flags.QR" => stats.flags.qr.parse(value),
-            "num.query.flags.AA" => stats.flags.aa.parse(value),
-            "num.query.flags.TC" => stats.flags.tc.parse(value),
-            "num.query.flags.RD" => stats.flags.rd.parse(value),
-            "num.query.flags.RA" => stats.flags.ra.parse(value),
-            "num.query.flags.Z" => stats.flags.z.parse(value),
-            "num.query.flags.AD" => stats.flags.ad.parse(value),
-            "num.query.flags.CD" => stats.flags.cd.parse(value),
-            "num.query.edns.present" => stats.num_query_edns_present.parse(value),
-            "num.query.edns.DO" => stats.num_query_edns_do.parse(value),
-            // `rcode.nodata` is ignored, same to `kumina/unbound_exporter`
-            "num.answer.rcode.nodata" => Ok(()),
-            key if key.starts_with("num.answer.rcode.") => {
-                let mut parts = key.rsplitn(2, '.');
-                let raw_code = parts.next().ok_or(ParseError::InvalidFormat)?;
-                let code = parse_rcode(raw_code)?;
-                let value = value.parse::<u64>()?;
-                let _ = stats.answer_rcodes.insert(code, value);
-                Ok(())
-            }
-            "num.query.ratelimited" => stats.num_query_rate_limited.parse(value),
-            "num.answer.secure" => stats.num_answer_secure.parse(value),
-            "num.answer.bogus" => stats.num_answer_bogus.parse(value),
-            "num.rrset.bogus" => stats.num_rrset_bogus.parse(value),
-            key if key.starts_with("num.query.aggressive.") => {
-                let mut parts = key.rsplitn(2, '.');
-                let raw_code = parts.next().ok_or(ParseError::InvalidFormat)?;
-                let code = parse_rcode(raw_code)?;
-                let value = value.parse::<u64>()?;
-                let _ = stats.query_aggressive.insert(code, value);
-                Ok(())
-            }
-            "unwanted.queries" => stats.num_unwanted_queries.parse(value),
-            "unwanted.replies" => stats.num_unwanted_replies.parse(value),
-            "msg.cache.count" => stats.cache_count.message.parse(value),
-            "rrset.cache.count" => stats.cache_count.rrset.parse(value),
-            "infra.cache.count" => stats.cache_count.infra.parse(value),
-            "key.cache.count" => stats.cache_count.key.parse(value),
-            "dnscrypt_shared_secret.cache.count" => stats.cache_count.dnscrypt_shared_secret.parse(value),
-            "dnscrypt_nonce.cache.count" => stats.cache_count.dnscrypt_nonce.parse(value),
-            "num.query.dnscrypt.shared_secret.cachemiss" => {
-                stats.num_query_dnscrypt_shared_secret_cache_miss.parse(value)
-            }
-            "num.query.dnscrypt.replay" => stats.num_query_dnscrypt_replay.parse(value),
-            "num.query.authzone.up" => stats.num_query_authzone_up.parse(value),
-            "num.query.authzone.down" => stats.num_query_authzone_down.parse(value),
-            "num.query.subnet" => stats.num_query_subnet.parse(value),
-            "num.query.subnet_cache" => stats.num_query_subnet_cache.parse(value),
-            _ => Err(ParseError::UnknownKey { key: key.into() }),
+        if let Some(raw_type) = key.strip_prefix("num.query.type.") {
+            let type_ = parse_rtype(raw_type).ok_or(ParseErrorKind::UnknownKey)?;
+            let _ = stats.query_types.insert(type_, types::parse_u64(value)?);
+        } else if let Some(raw_class) = key.strip_prefix("num.query.class.") {
+            let class = parse_class(raw_class).ok_or(ParseErrorKind::UnknownKey)?;
+            let _ = stats.query_classes.insert(class, types::parse_u64(value)?);
+        } else if let Some(raw_code) = key.strip_prefix("num.query.opcode.") {
+            let code = parse_opcode(raw_code).ok_or(ParseErrorKind::UnknownKey)?;
+            let _ = stats.query_opcodes.insert(code, types::parse_u64(value)?);
+        } else if let Some(raw_code) = key.strip_prefix("num.answer.rcode.") {
+            let code = parse_rcode(raw_code).map_err(|_| ParseErrorKind::UnknownKey)?;
+            let _ = stats.answer_rcodes.insert(code, types::parse_u64(value)?);
+        } else if let Some(raw_code) = key.strip_prefix("num.answer.ede.") {
+            let code = raw_code.parse::<u16>().map_err(|_| ParseErrorKind::UnknownKey)?;
+            let _ = stats.answer_ede.insert(code, types::parse_u64(value)?);
+        } else if let Some(raw_code) = key.strip_prefix("num.query.aggressive.") {
+            let code = parse_rcode(raw_code).map_err(|_| ParseErrorKind::UnknownKey)?;
+            let _ = stats.query_aggressive.insert(code, types::parse_u64(value)?);
+        } else if let Some(raw_action) = key.strip_prefix("num.rpz.action.") {
+            let action = RpzAction::from_str(raw_action).map_err(|_| ParseErrorKind::UnknownKey)?;
+            let _ = stats.rpz_actions.insert(action, types::parse_u64(value)?);
+        } else {
+            return Err(ParseErrorKind::UnknownKey);
         }
+
+        Ok(())
     }
 
-    fn histogram(hist: &mut Histogram, key: &str, value: &str) -> Result<(), ParseError> {
-        let mut parts = key.splitn(4, '.').skip(3);
-        let time = parts.next().ok_or(ParseError::InvalidFormat)?.parse::<f64>()?;
+    fn histogram(hist: &mut Histogram, key: &str, value: &str) -> Result<(), ParseErrorKind> {
+        // Key is in the `000000.000000.to.000000.000001` format, only the upper bound is needed
+        let (_, raw_le) = key
+            .bytes()
+            .enumerate()
+            .filter(|(_, byte)| *byte == b'.')
+            .nth(2)
+            .map(|(idx, _)| key.split_at(idx + 1))
+            .ok_or(ParseErrorKind::InvalidFormat)?;
 
-        let duration = Duration::checked_from_secs_f64(time).ok_or(ParseError::InvalidFormat)?;
-        let value = value.parse()?;
+        let duration = types::parse_duration(raw_le)?;
+        let value = types::parse_u64(value)?;
 
         hist.push(duration, value);
 
@@ -245,11 +260,20 @@ impl Parser {
     }
 }
 
+/// Splits the `s` at the first occurrence of the ASCII `delimiter`.
+///
+/// Lines are short, so the plain bytes scan outperforms the `str::splitn` machinery.
+#[inline]
+fn split_once(s: &str, delimiter: u8) -> Option<(&str, &str)> {
+    debug_assert!(delimiter.is_ascii());
+    let idx = s.bytes().position(|byte| byte == delimiter)?;
+
+    Some((&s[..idx], &s[idx + 1..]))
+}
+
 impl Default for Parser {
     fn default() -> Self {
-        Parser {
-            stats: Statistics::default(),
-        }
+        Parser::with_mode(ParseMode::default())
     }
 }
 
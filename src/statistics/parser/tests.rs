@@ -1,7 +1,7 @@
-use claim::{assert_ok, assert_some_eq};
+use claim::{assert_err, assert_ok, assert_some_eq};
 
-use super::Parser;
-use crate::statistics::{Class, Rcode, Rtype};
+use super::{ParseErrorKind, ParseMode, Parser};
+use crate::statistics::{Class, Opcode, Rcode, RpzAction, Rtype};
 
 static STATS: &str = include_str!("../../../assets/test_text_stats.txt");
 static STATS_1_13_2: &str = include_str!("../../../assets/test_text_stats_1_13_2.txt");
@@ -93,5 +93,243 @@ fn test_parser() {
 fn test_parser_1_13_2_format() {
     let parser = Parser::new();
 
-    assert_ok!(parser.parse(STATS_1_13_2));
+    let stats = assert_ok!(parser.parse(STATS_1_13_2));
+    assert_some_eq!(stats.query_types.get(&Rtype::from_int(65)), &10);
+    assert_some_eq!(stats.query_types.get(&Rtype::from_int(64)), &45);
+    assert!(stats.unknown.is_empty());
+}
+
+#[test]
+fn test_parser_generic_syntax() {
+    let parser = Parser::new();
+    let stats = parser
+        .parse(
+            "num.query.type.TYPE65534=4
+num.query.type.type64=2
+num.query.class.CLASS254=1
+num.query.opcode.OPCODE3=7",
+        )
+        .unwrap();
+
+    assert_some_eq!(stats.query_types.get(&Rtype::from_int(65534)), &4);
+    assert_some_eq!(stats.query_types.get(&Rtype::from_int(64)), &2);
+    assert_some_eq!(stats.query_classes.get(&Class::None), &1);
+    assert_some_eq!(stats.query_opcodes.get(&Opcode::from_int(3)), &7);
+    assert!(stats.unknown.is_empty());
+}
+
+#[test]
+fn test_parser_rpz_actions() {
+    let parser = Parser::new();
+    let stats = parser
+        .parse(
+            "num.rpz.action.nxdomain=12
+num.rpz.action.nodata=3
+num.rpz.action.passthru=7
+num.rpz.action.drop=1
+num.rpz.action.tcp-only=0
+num.rpz.action.local-data=5
+num.rpz.action.disabled=0
+num.rpz.action.cname-override=2",
+        )
+        .unwrap();
+
+    assert_eq!(stats.rpz_actions.len(), 8);
+    assert_some_eq!(stats.rpz_actions.get(&RpzAction::NxDomain), &12);
+    assert_some_eq!(stats.rpz_actions.get(&RpzAction::NoData), &3);
+    assert_some_eq!(stats.rpz_actions.get(&RpzAction::PassThru), &7);
+    assert_some_eq!(stats.rpz_actions.get(&RpzAction::Drop), &1);
+    assert_some_eq!(stats.rpz_actions.get(&RpzAction::TcpOnly), &0);
+    assert_some_eq!(stats.rpz_actions.get(&RpzAction::LocalData), &5);
+    assert_some_eq!(stats.rpz_actions.get(&RpzAction::Disabled), &0);
+    assert_some_eq!(stats.rpz_actions.get(&RpzAction::CnameOverride), &2);
+}
+
+#[test]
+fn test_parser_modules() {
+    let parser = Parser::new();
+    let stats = parser
+        .parse(
+            "mem.mod.iterator=16588
+mem.mod.validator=140392
+mem.mod.respip=0
+mem.mod.subnet=74504
+mem.mod.ipsecmod=8
+mem.mod.dynlibmod=16
+mem.mod.cachedb=1024
+num.query.subnet=12
+num.query.subnet_cache=4
+num.query.cachedb=42
+num.answer.ede.0=3
+num.answer.ede.22=9",
+        )
+        .unwrap();
+
+    assert_eq!(stats.modules.subnet, 74504);
+    assert_eq!(stats.modules.ipsecmod, 8);
+    assert_eq!(stats.modules.dynlibmod, 16);
+    assert_eq!(stats.modules.cachedb, 1024);
+    assert_eq!(stats.num_query_subnet, 12);
+    assert_eq!(stats.num_query_subnet_cache, 4);
+    assert_eq!(stats.num_query_cachedb, 42);
+    assert_some_eq!(stats.answer_ede.get(&0), &3);
+    assert_some_eq!(stats.answer_ede.get(&22), &9);
+}
+
+#[test]
+fn test_parser_unknown_keys() {
+    let input = "thread0.num.queries=696
+thread0.num.brand_new=1
+num.query.type.A=10
+num.brand.new.key=42
+num.query.type.WHATEVER=3";
+
+    let stats = Parser::new().parse(input).unwrap();
+
+    assert_eq!(stats.threads[0].num_queries, 696);
+    assert_some_eq!(stats.query_types.get(&Rtype::A), &10);
+    assert_eq!(
+        stats.unknown,
+        vec![
+            ("thread0.num.brand_new".to_string(), "1".to_string()),
+            ("num.brand.new.key".to_string(), "42".to_string()),
+            ("num.query.type.WHATEVER".to_string(), "3".to_string()),
+        ]
+    );
+
+    let e = assert_err!(Parser::with_mode(ParseMode::Strict).parse(input));
+    assert!(matches!(e.kind(), ParseErrorKind::UnknownKey));
+    assert_eq!(e.line(), 2);
+    assert_eq!(e.key(), "thread0.num.brand_new");
+    assert_eq!(e.value(), "1");
+}
+
+#[test]
+fn test_parser_error_context() {
+    let input = "thread0.num.queries=696
+
+thread0.num.cachehits=11x9
+thread0.num.cachemiss=577";
+
+    let e = assert_err!(Parser::new().parse(input));
+    assert!(matches!(e.kind(), ParseErrorKind::ParseInt(..)));
+    assert_eq!(e.line(), 3);
+    assert_eq!(e.key(), "thread0.num.cachehits");
+    assert_eq!(e.value(), "11x9");
+    assert_eq!(
+        e.to_string(),
+        "invalid digit found in string at line 3 (key 'thread0.num.cachehits', value '11x9')"
+    );
+
+    let e = assert_err!(Parser::new().parse("time.now=1580162982.980833\ntime.up"));
+    assert!(matches!(e.kind(), ParseErrorKind::MissingValue));
+    assert_eq!(e.line(), 2);
+    assert_eq!(e.key(), "time.up");
+
+    let e = assert_err!(Parser::new().parse("=42"));
+    assert!(matches!(e.kind(), ParseErrorKind::MissingKey));
+    assert_eq!(e.value(), "42");
+}
+
+#[test]
+fn test_parser_lenient() {
+    let input = "thread0.num.queries=696
+thread0.num.cachehits=11x9
+thread0.num.brand_new=1
+thread0.num.cachemiss=577
+time.now=";
+
+    let stats = Parser::with_mode(ParseMode::Lenient).parse(input).unwrap();
+
+    assert_eq!(stats.threads[0].num_queries, 696);
+    assert_eq!(stats.threads[0].num_cache_miss, 577);
+    assert_eq!(stats.unknown.len(), 1);
+    assert_eq!(stats.diagnostics.len(), 2);
+    assert_eq!(stats.diagnostics[0].line(), 2);
+    assert_eq!(stats.diagnostics[0].key(), "thread0.num.cachehits");
+    assert_eq!(stats.diagnostics[1].line(), 5);
+    assert_eq!(stats.diagnostics[1].key(), "time.now");
+}
+
+#[tokio::test]
+async fn test_parser_reader() {
+    let stats = assert_ok!(Parser::new().parse_reader(STATS.as_bytes()).await);
+
+    assert_eq!(stats.threads.len(), 2);
+    assert_eq!(stats.total.num_queries, 1338);
+    assert_some_eq!(stats.query_types.get(&Rtype::A), &4576639648);
+    assert_eq!(
+        stats.histogram.count(),
+        Parser::new().parse(STATS).unwrap().histogram.count()
+    );
+}
+
+#[tokio::test]
+async fn test_parser_reader_errors() {
+    let input: &[u8] = b"thread0.num.queries=696\nthread0.num.cachehits=11x9\n";
+    let e = assert_err!(Parser::new().parse_reader(input).await);
+    assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+    assert_eq!(
+        e.to_string(),
+        "invalid digit found in string at line 2 (key 'thread0.num.cachehits', value '11x9')"
+    );
+
+    let limit = STATS.len() - 1;
+    let e = assert_err!(Parser::new().max_size(limit).parse_reader(STATS.as_bytes()).await);
+    assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+    assert!(e.to_string().starts_with("Statistics data exceeds the limit of"));
+
+    assert_ok!(Parser::new().max_size(STATS.len()).parse_reader(STATS.as_bytes()).await);
+    assert_err!(Parser::new().max_size(limit).parse(STATS));
+}
+
+#[test]
+fn test_dispatch_tables_sorted() {
+    fn is_sorted<T>(table: &[(&str, T)]) -> bool {
+        table
+            .windows(2)
+            .all(|pair| super::keys::compare(pair[0].0, pair[1].0) == std::cmp::Ordering::Less)
+    }
+
+    assert!(is_sorted(super::keys::THREAD));
+    assert!(is_sorted(super::keys::OTHER));
+}
+
+#[test]
+fn test_parse_numbers() {
+    use super::types::{parse_duration, parse_u64};
+    use std::time::Duration;
+
+    assert_eq!(assert_ok!(parse_u64("0")), 0);
+    assert_eq!(assert_ok!(parse_u64("1234567890")), 1_234_567_890);
+    assert_eq!(assert_ok!(parse_u64("18446744073709551615")), u64::MAX);
+    assert_eq!(assert_ok!(parse_u64("+5")), 5);
+    assert_err!(parse_u64(""));
+    assert_err!(parse_u64("-1"));
+    assert_err!(parse_u64("12a"));
+    assert_err!(parse_u64("18446744073709551616"));
+
+    assert_eq!(assert_ok!(parse_duration("0.000064")), Duration::from_micros(64));
+    assert_eq!(
+        assert_ok!(parse_duration("524288.000000")),
+        Duration::from_secs(524_288)
+    );
+    assert_eq!(assert_ok!(parse_duration("12.5")), Duration::from_millis(12_500));
+    assert_eq!(assert_ok!(parse_duration("12")), Duration::from_secs(12));
+    assert_eq!(assert_ok!(parse_duration("1e-3")), Duration::from_millis(1));
+    assert_err!(parse_duration("-1.0"));
+    assert_err!(parse_duration("abc"));
+}
+
+#[test]
+fn test_parser_thread_order() {
+    let stats = "thread1.num.queries=5
+thread0.num.queries=3
+thread1.tcpusage=2
+";
+    let stats = assert_ok!(Parser::new().parse(stats));
+    assert_eq!(stats.threads.len(), 2);
+    assert_eq!(stats.threads[0].num_queries, 3);
+    assert_eq!(stats.threads[1].num_queries, 5);
+    assert_eq!(stats.threads[1].tcp_usage, 2);
 }
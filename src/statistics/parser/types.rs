@@ -1,41 +1,98 @@
-use std::str::FromStr;
 use std::time::Duration;
 
-use domain::base::iana::{Class, Rcode};
+use domain::base::iana::Rcode;
 
-use crate::statistics::ParseError;
-use domain::base::Rtype;
+use super::ParseErrorKind;
+use crate::statistics::iana;
 
 pub(crate) trait Field: Sized {
-    fn parse(&mut self, s: &str) -> Result<(), ParseError>;
+    fn parse(&mut self, s: &str) -> Result<(), ParseErrorKind>;
 }
 
 impl Field for u64 {
-    fn parse(&mut self, s: &str) -> Result<(), ParseError> {
-        *self = s.parse().map_err(ParseError::from)?;
+    fn parse(&mut self, s: &str) -> Result<(), ParseErrorKind> {
+        *self = parse_u64(s)?;
         Ok(())
     }
 }
 
 impl Field for f64 {
-    fn parse(&mut self, s: &str) -> Result<(), ParseError> {
-        *self = s.parse().map_err(ParseError::from)?;
+    fn parse(&mut self, s: &str) -> Result<(), ParseErrorKind> {
+        *self = s.parse().map_err(ParseErrorKind::from)?;
         Ok(())
     }
 }
 
 impl Field for Duration {
-    fn parse(&mut self, s: &str) -> Result<(), ParseError> {
+    fn parse(&mut self, s: &str) -> Result<(), ParseErrorKind> {
+        *self = parse_duration(s)?;
+        Ok(())
+    }
+}
+
+/// Parses the decimal digits directly from the bytes.
+///
+/// Anything unusual (sign, overflow, empty string) is handed over to the `str::parse`,
+/// so the values and errors are exactly the same as with the `std` implementation.
+#[inline]
+pub(crate) fn parse_u64(s: &str) -> Result<u64, ParseErrorKind> {
+    let bytes = s.as_bytes();
+    if bytes.is_empty() || bytes.len() > 19 {
+        return s.parse().map_err(ParseErrorKind::from);
+    }
+
+    let mut value = 0u64;
+    for &byte in bytes {
+        let digit = byte.wrapping_sub(b'0');
+        if digit > 9 {
+            return s.parse().map_err(ParseErrorKind::from);
+        }
+        // 19 digits are always fitting into the `u64`
+        value = value * 10 + u64::from(digit);
+    }
+
+    Ok(value)
+}
+
+/// Parses the `secs.fraction` values printed by `unbound` (ex. `0.000064` or `12.5`)
+/// without going through the `f64`.
+///
+/// Everything else (exponents, signs, more than nine fraction digits)
+/// falls back to the `f64` parsing.
+#[inline]
+pub(crate) fn parse_duration(s: &str) -> Result<Duration, ParseErrorKind> {
+    let fallback = || {
         let value = s.parse::<f64>()?;
+        Duration::checked_from_secs_f64(value).ok_or(ParseErrorKind::InvalidFormat)
+    };
 
-        match Duration::checked_from_secs_f64(value) {
-            Some(duration) => {
-                *self = duration;
-                Ok(())
-            }
-            None => Err(ParseError::InvalidFormat),
+    let bytes = s.as_bytes();
+    let dot = bytes.iter().position(|&byte| byte == b'.').unwrap_or(bytes.len());
+    let (raw_secs, raw_frac) = (&bytes[..dot], bytes.get(dot + 1..).unwrap_or_default());
+    if raw_secs.is_empty() || raw_secs.len() > 19 || raw_frac.len() > 9 {
+        return fallback();
+    }
+
+    let mut secs = 0u64;
+    for &byte in raw_secs {
+        let digit = byte.wrapping_sub(b'0');
+        if digit > 9 {
+            return fallback();
         }
+        secs = secs * 10 + u64::from(digit);
+    }
+
+    let mut nanos = 0u32;
+    for idx in 0..9 {
+        let digit = match raw_frac.get(idx) {
+            Some(byte) if byte.is_ascii_digit() => byte - b'0',
+            Some(_) => return fallback(),
+            None => 0,
+        };
+        nanos = nanos * 10 + u32::from(digit);
     }
+
+    Ok(Duration::new(secs, nanos))
 }
 
 pub trait DurationExt {
@@ -67,45 +124,8 @@ pub trait DurationExt {
 
 impl DurationExt for Duration {}
 
-/// `Rcode` enum from `domain` crate does not implement `FromStr`,
-/// but we need it to parse textual representation from `unbound`.
-///
-/// Considering that text format is known, current implementation
-/// is pretty simple and even skips the case-sensitive checks.
-pub(crate) fn parse_rcode(s: &str) -> Result<Rcode, ParseError> {
-    match s {
-        "NOERROR" => Ok(Rcode::NoError),
-        "FORMERR" => Ok(Rcode::FormErr),
-        "SERVFAIL" => Ok(Rcode::ServFail),
-        "NXDOMAIN" => Ok(Rcode::NXDomain),
-        "NOTIMPL" => Ok(Rcode::NotImp),
-        "REFUSED" => Ok(Rcode::Refused),
-        "YXDOMAIN" => Ok(Rcode::YXDomain),
-        "YXRRSET" => Ok(Rcode::YXRRSet),
-        "NXRRSET" => Ok(Rcode::NXRRSet),
-        "NOTAUTH" => Ok(Rcode::NotAuth),
-        "NOTZONE" => Ok(Rcode::NotZone),
-        _ => Err(ParseError::ParseStr(s.to_owned())),
-    }
-}
-
-/// `Class` enum from `domain` crate uses `"*"` as a text representation for `Class::Any`
-/// enum member.
-///
-/// While this is obviously correct behavior, text representation of `unbound` statistics
-/// outputs `"ANY"` instead of `"*"`, which means we needs to handle this case separately.
-pub(crate) fn parse_class(s: &str) -> Result<Class, ParseError> {
-    match s {
-        "ANY" => Ok(Class::Any),
-        other => Class::from_str(other).map_err(|e| ParseError::ParseStr(format!("{}", e))),
-    }
-}
-
-/// `Rtype` enum from `domain` crate have some differences with query types `unbound` returns,
-/// so we need to map them.
-pub(crate) fn parse_rtype(s: &str) -> Result<Rtype, ParseError> {
-    match s {
-        "NSAP-PTR" => Ok(Rtype::Nsapptr),
-        other => Rtype::from_str(other).map_err(|e| ParseError::ParseStr(format!("{}", e))),
-    }
+/// `Rcode` enum from `domain` crate does not implement `FromStr`
+/// and is using different mnemonics, so the `unbound` ones are used instead.
+pub(crate) fn parse_rcode(s: &str) -> Result<Rcode, ParseErrorKind> {
+    iana::parse_rcode(s).ok_or_else(|| ParseErrorKind::ParseStr(s.to_owned()))
 }
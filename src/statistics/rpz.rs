@@ -0,0 +1,49 @@
+use std::str::FromStr;
+
+/// Action applied by the Response Policy Zone (RPZ) policy,
+/// as reported via the `num.rpz.action.*` keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RpzAction {
+    NxDomain,
+    NoData,
+    PassThru,
+    Drop,
+    TcpOnly,
+    LocalData,
+    Disabled,
+    CnameOverride,
+}
+
+impl RpzAction {
+    /// Action name in the same format `unbound` uses for the statistics keys.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RpzAction::NxDomain => "nxdomain",
+            RpzAction::NoData => "nodata",
+            RpzAction::PassThru => "passthru",
+            RpzAction::Drop => "drop",
+            RpzAction::TcpOnly => "tcp-only",
+            RpzAction::LocalData => "local-data",
+            RpzAction::Disabled => "disabled",
+            RpzAction::CnameOverride => "cname-override",
+        }
+    }
+}
+
+impl FromStr for RpzAction {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "nxdomain" => Ok(RpzAction::NxDomain),
+            "nodata" => Ok(RpzAction::NoData),
+            "passthru" => Ok(RpzAction::PassThru),
+            "drop" => Ok(RpzAction::Drop),
+            "tcp-only" => Ok(RpzAction::TcpOnly),
+            "local-data" => Ok(RpzAction::LocalData),
+            "disabled" => Ok(RpzAction::Disabled),
+            "cname-override" => Ok(RpzAction::CnameOverride),
+            _ => Err(()),
+        }
+    }
+}
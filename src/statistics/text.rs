@@ -0,0 +1,538 @@
+//! Serialization of the [`Statistics`] back into the `unbound-control stats_noreset` text format.
+//!
+//! Keys are written in the same order as `unbound` does (see `print_stats`, `print_mem`,
+//! `print_hist` and `print_ext` functions in the `smallapp/unbound-control.c`),
+//! so the output can be fed into anything which expects the native `unbound` data.
+//!
+//! Output is round-trippable through the `Statistics::from_str`, with a few exceptions:
+//!  * `num.answer.rcode.nodata` is ignored by the parser and therefore is never written,
+//!  * `Statistics::diagnostics` are not part of the data and are skipped.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+use std::io;
+use std::time::Duration;
+
+use super::iana;
+use super::{Statistics, Thread};
+
+impl Statistics {
+    /// Write the statistics in the `unbound` text format into the `writer`.
+    pub fn write_text<W: io::Write>(&self, mut writer: W) -> io::Result<()> {
+        write!(writer, "{}", self)
+    }
+}
+
+impl fmt::Display for Statistics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (idx, thread) in self.threads.iter().enumerate() {
+            write_thread(f, format_args!("thread{}", idx), thread)?;
+        }
+        write_thread(f, format_args!("total"), &self.total)?;
+
+        writeln!(f, "time.now={}", Secs(self.time.now))?;
+        writeln!(f, "time.up={}", Secs(self.time.up))?;
+        writeln!(f, "time.elapsed={}", Secs(self.time.elapsed))?;
+
+        writeln!(f, "mem.cache.rrset={}", self.cache.rrset)?;
+        writeln!(f, "mem.cache.message={}", self.cache.message)?;
+        writeln!(f, "mem.mod.iterator={}", self.modules.iterator)?;
+        writeln!(f, "mem.mod.validator={}", self.modules.validator)?;
+        writeln!(f, "mem.mod.respip={}", self.modules.respip)?;
+        writeln!(f, "mem.mod.subnet={}", self.modules.subnet)?;
+        writeln!(f, "mem.mod.ipsecmod={}", self.modules.ipsecmod)?;
+        writeln!(f, "mem.mod.dynlibmod={}", self.modules.dynlibmod)?;
+        writeln!(f, "mem.mod.cachedb={}", self.modules.cachedb)?;
+        writeln!(
+            f,
+            "mem.cache.dnscrypt_shared_secret={}",
+            self.cache.dnscrypt_shared_secret
+        )?;
+        writeln!(f, "mem.cache.dnscrypt_nonce={}", self.cache.dnscrypt_nonce)?;
+        writeln!(f, "mem.streamwait={}", self.mem_streamwait)?;
+        writeln!(f, "mem.http.query_buffer={}", self.http.query_buffer)?;
+        writeln!(f, "mem.http.response_buffer={}", self.http.response_buffer)?;
+
+        let mut lower = Duration::from_secs(0);
+        for (upper, count) in self.histogram.raw_buckets() {
+            writeln!(f, "histogram.{}.to.{}={}", Bound(lower), Bound(upper), count)?;
+            lower = upper;
+        }
+
+        for (rtype, value) in sorted(&self.query_types, |rtype| rtype.to_int()) {
+            writeln!(f, "num.query.type.{}={}", iana::rtype_to_str(*rtype), value)?;
+        }
+        writeln!(f, "num.query.type.other={}", self.query_types_other)?;
+        for (class, value) in sorted(&self.query_classes, |class| class.to_int()) {
+            writeln!(f, "num.query.class.{}={}", iana::class_to_str(*class), value)?;
+        }
+        writeln!(f, "num.query.class.other={}", self.query_classes_other)?;
+        for (opcode, value) in sorted(&self.query_opcodes, |opcode| opcode.to_int()) {
+            writeln!(f, "num.query.opcode.{}={}", iana::opcode_to_str(*opcode), value)?;
+        }
+
+        writeln!(f, "num.query.tcp={}", self.num_query_tcp)?;
+        writeln!(f, "num.query.tcpout={}", self.num_query_tcp_out)?;
+        writeln!(f, "num.query.tls={}", self.num_query_tls)?;
+        writeln!(f, "num.query.tls.resume={}", self.num_query_tls_resume)?;
+        writeln!(f, "num.query.ipv6={}", self.num_query_ipv6)?;
+        writeln!(f, "num.query.https={}", self.num_query_https)?;
+        writeln!(f, "num.query.flags.QR={}", self.flags.qr)?;
+        writeln!(f, "num.query.flags.AA={}", self.flags.aa)?;
+        writeln!(f, "num.query.flags.TC={}", self.flags.tc)?;
+        writeln!(f, "num.query.flags.RD={}", self.flags.rd)?;
+        writeln!(f, "num.query.flags.RA={}", self.flags.ra)?;
+        writeln!(f, "num.query.flags.Z={}", self.flags.z)?;
+        writeln!(f, "num.query.flags.AD={}", self.flags.ad)?;
+        writeln!(f, "num.query.flags.CD={}", self.flags.cd)?;
+        writeln!(f, "num.query.edns.present={}", self.num_query_edns_present)?;
+        writeln!(f, "num.query.edns.DO={}", self.num_query_edns_do)?;
+
+        for (rcode, value) in sorted(&self.answer_rcodes, |rcode| rcode.to_int()) {
+            writeln!(f, "num.answer.rcode.{}={}", iana::rcode_to_str(*rcode), value)?;
+        }
+        for (code, value) in sorted(&self.answer_ede, |code| *code) {
+            writeln!(f, "num.answer.ede.{}={}", code, value)?;
+        }
+        writeln!(f, "num.query.ratelimited={}", self.num_query_rate_limited)?;
+        writeln!(f, "num.answer.secure={}", self.num_answer_secure)?;
+        writeln!(f, "num.answer.bogus={}", self.num_answer_bogus)?;
+        writeln!(f, "num.rrset.bogus={}", self.num_rrset_bogus)?;
+        for (rcode, value) in sorted(&self.query_aggressive, |rcode| rcode.to_int()) {
+            writeln!(f, "num.query.aggressive.{}={}", iana::rcode_to_str(*rcode), value)?;
+        }
+        writeln!(f, "unwanted.queries={}", self.num_unwanted_queries)?;
+        writeln!(f, "unwanted.replies={}", self.num_unwanted_replies)?;
+
+        writeln!(f, "msg.cache.count={}", self.cache_count.message)?;
+        writeln!(f, "rrset.cache.count={}", self.cache_count.rrset)?;
+        writeln!(f, "infra.cache.count={}", self.cache_count.infra)?;
+        writeln!(f, "key.cache.count={}", self.cache_count.key)?;
+        writeln!(
+            f,
+            "dnscrypt_shared_secret.cache.count={}",
+            self.cache_count.dnscrypt_shared_secret
+        )?;
+        writeln!(f, "dnscrypt_nonce.cache.count={}", self.cache_count.dnscrypt_nonce)?;
+        writeln!(
+            f,
+            "num.query.dnscrypt.shared_secret.cachemiss={}",
+            self.num_query_dnscrypt_shared_secret_cache_miss
+        )?;
+        writeln!(f, "num.query.dnscrypt.replay={}", self.num_query_dnscrypt_replay)?;
+        writeln!(f, "num.query.authzone.up={}", self.num_query_authzone_up)?;
+        writeln!(f, "num.query.authzone.down={}", self.num_query_authzone_down)?;
+        writeln!(f, "num.query.subnet={}", self.num_query_subnet)?;
+        writeln!(f, "num.query.subnet_cache={}", self.num_query_subnet_cache)?;
+        writeln!(f, "num.query.cachedb={}", self.num_query_cachedb)?;
+        for (action, value) in sorted(&self.rpz_actions, |action| action.as_str()) {
+            writeln!(f, "num.rpz.action.{}={}", action.as_str(), value)?;
+        }
+
+        // Keys unknown to the parser are written as is, so they would not be lost in transit
+        for (key, value) in &self.unknown {
+            writeln!(f, "{}={}", key, value)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn write_thread(f: &mut fmt::Formatter, prefix: fmt::Arguments, thread: &Thread) -> fmt::Result {
+    writeln!(f, "{}.num.queries={}", prefix, thread.num_queries)?;
+    writeln!(
+        f,
+        "{}.num.queries_ip_ratelimited={}",
+        prefix, thread.num_queries_ip_ratelimited
+    )?;
+    writeln!(f, "{}.num.cachehits={}", prefix, thread.num_cache_hits)?;
+    writeln!(f, "{}.num.cachemiss={}", prefix, thread.num_cache_miss)?;
+    writeln!(f, "{}.num.prefetch={}", prefix, thread.num_prefetch)?;
+    // Key name used since `unbound` 1.10.1, `num.zero_ttl` is still understood by the parser
+    writeln!(f, "{}.num.expired={}", prefix, thread.num_zero_ttl)?;
+    writeln!(f, "{}.num.recursivereplies={}", prefix, thread.num_recursive_replies)?;
+    writeln!(f, "{}.num.dnscrypt.crypted={}", prefix, thread.num_dnscrypt_crypted)?;
+    writeln!(f, "{}.num.dnscrypt.cert={}", prefix, thread.num_dnscrypt_cert)?;
+    writeln!(f, "{}.num.dnscrypt.cleartext={}", prefix, thread.num_dnscrypt_cleartext)?;
+    writeln!(f, "{}.num.dnscrypt.malformed={}", prefix, thread.num_dnscrypt_malformed)?;
+    writeln!(f, "{}.requestlist.avg={}", prefix, thread.requestlist_avg)?;
+    writeln!(f, "{}.requestlist.max={}", prefix, thread.requestlist_max)?;
+    writeln!(
+        f,
+        "{}.requestlist.overwritten={}",
+        prefix, thread.requestlist_overwritten
+    )?;
+    writeln!(f, "{}.requestlist.exceeded={}", prefix, thread.requestlist_exceeded)?;
+    writeln!(
+        f,
+        "{}.requestlist.current.all={}",
+        prefix, thread.requestlist_current_all
+    )?;
+    writeln!(
+        f,
+        "{}.requestlist.current.user={}",
+        prefix, thread.requestlist_current_user
+    )?;
+    writeln!(f, "{}.recursion.time.avg={}", prefix, Micros(thread.recursion_time_avg))?;
+    writeln!(f, "{}.recursion.time.median={}", prefix, thread.recursion_time_median)?;
+    writeln!(f, "{}.tcpusage={}", prefix, thread.tcp_usage)
+}
+
+/// `HashMap` entries ordered by the `key` function, same as `unbound` prints them.
+fn sorted<K, V, F, O>(map: &HashMap<K, V>, key: F) -> Vec<(&K, &V)>
+where
+    K: Eq + Hash,
+    F: Fn(&K) -> O,
+    O: Ord,
+{
+    let mut entries = map.iter().collect::<Vec<_>>();
+    entries.sort_by_key(|(k, _)| key(k));
+    entries
+}
+
+/// Seconds with the microseconds precision, as `unbound` prints the timestamps.
+///
+/// Sub-microsecond precision is not available from `unbound`,
+/// but if it is there, all nine digits are written in order not to lose it.
+struct Secs(Duration);
+
+impl fmt::Display for Secs {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.", self.0.as_secs())?;
+        Fraction(self.0).fmt(f)
+    }
+}
+
+/// `unbound` prints the average recursion time as seconds with the microseconds precision;
+/// more precise values are written as is in order not to lose it.
+struct Micros(f64);
+
+impl fmt::Display for Micros {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let fixed = format!("{:.6}", self.0);
+        match fixed.parse::<f64>() {
+            Ok(value) if value.to_bits() == self.0.to_bits() => f.write_str(&fixed),
+            _ => write!(f, "{}", self.0),
+        }
+    }
+}
+
+/// Histogram bucket bound, with the zero-padded seconds.
+struct Bound(Duration);
+
+impl fmt::Display for Bound {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:06}.", self.0.as_secs())?;
+        Fraction(self.0).fmt(f)
+    }
+}
+
+struct Fraction(Duration);
+
+impl fmt::Display for Fraction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let micros = self.0.subsec_micros();
+        let nanos = self.0.subsec_nanos();
+        if micros * 1_000 == nanos {
+            write!(f, "{:06}", micros)
+        } else {
+            write!(f, "{:09}", nanos)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+    use std::time::Duration;
+
+    use claim::assert_ok;
+    use domain::base::iana::{Class, Opcode, Rcode, Rtype};
+
+    use crate::statistics::{Histogram, RpzAction, Statistics, Thread};
+
+    static STATS: &str = include_str!("../../assets/test_text_stats.txt");
+    static STATS_1_13_2: &str = include_str!("../../assets/test_text_stats_1_13_2.txt");
+
+    /// Number of the random statistics checked by the property tests.
+    const CASES: u64 = 256;
+
+    /// `xorshift64*` generator, good enough to produce the test data
+    /// and keeps the failing cases reproducible by their seed.
+    struct Rng(u64);
+
+    impl Rng {
+        fn new(seed: u64) -> Rng {
+            // State should never be zero
+            Rng(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+        }
+
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 >> 12;
+            self.0 ^= self.0 << 25;
+            self.0 ^= self.0 >> 27;
+            self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+        }
+
+        fn below(&mut self, bound: u64) -> u64 {
+            self.next() % bound
+        }
+
+        /// Mostly small values, as `unbound` have, but sometimes the extreme ones.
+        fn counter(&mut self) -> u64 {
+            match self.below(8) {
+                0 => 0,
+                1 => u64::MAX - self.below(16),
+                _ => self.below(1_000_000),
+            }
+        }
+
+        /// Any finite value, including the ones with a lot of significant digits.
+        fn float(&mut self) -> f64 {
+            loop {
+                let value = match self.below(3) {
+                    0 => self.below(1_000_000) as f64 / 1_000_000.0,
+                    1 => self.below(100_000) as f64 / 7.0,
+                    _ => f64::from_bits(self.next()),
+                };
+                if value.is_finite() {
+                    return value;
+                }
+            }
+        }
+
+        fn duration(&mut self) -> Duration {
+            let nanos = match self.below(2) {
+                // `unbound` has the microseconds precision
+                0 => self.below(1_000_000) as u32 * 1_000,
+                _ => self.below(1_000_000_000) as u32,
+            };
+            Duration::new(self.below(1_000_000_000_000_000_000), nanos)
+        }
+
+        fn thread(&mut self) -> Thread {
+            Thread {
+                num_queries: self.counter(),
+                num_queries_ip_ratelimited: self.counter(),
+                num_cache_hits: self.counter(),
+                num_cache_miss: self.counter(),
+                num_prefetch: self.counter(),
+                num_zero_ttl: self.counter(),
+                num_recursive_replies: self.counter(),
+                num_dnscrypt_crypted: self.counter(),
+                num_dnscrypt_cert: self.counter(),
+                num_dnscrypt_cleartext: self.counter(),
+                num_dnscrypt_malformed: self.counter(),
+                requestlist_avg: self.float(),
+                requestlist_max: self.counter(),
+                requestlist_overwritten: self.counter(),
+                requestlist_exceeded: self.counter(),
+                requestlist_current_all: self.counter(),
+                requestlist_current_user: self.counter(),
+                recursion_time_avg: self.float(),
+                recursion_time_median: self.float(),
+                tcp_usage: self.counter(),
+                ..Default::default()
+            }
+        }
+
+        fn statistics(&mut self) -> Statistics {
+            let mut stats = Statistics {
+                total: self.thread(),
+                ..Default::default()
+            };
+            for _ in 0..self.below(5) {
+                stats.threads.push(self.thread());
+            }
+
+            stats.time.now = self.duration();
+            stats.time.up = self.duration();
+            stats.time.elapsed = self.duration();
+
+            // Parser is taking the histogram average from the total recursion time
+            stats.histogram = Histogram::new(stats.total.recursion_time_avg);
+            for _ in 0..self.below(41) {
+                let le = self.duration();
+                let count = self.counter();
+                stats.histogram.push(le, count);
+            }
+
+            for _ in 0..self.below(20) {
+                let key = Rtype::from_int(self.next() as u16);
+                let value = self.counter();
+                let _ = stats.query_types.insert(key, value);
+            }
+            for _ in 0..self.below(5) {
+                let key = Class::from_int(self.next() as u16);
+                let value = self.counter();
+                let _ = stats.query_classes.insert(key, value);
+            }
+            for _ in 0..self.below(5) {
+                let key = Opcode::from_int(self.below(16) as u8);
+                let value = self.counter();
+                let _ = stats.query_opcodes.insert(key, value);
+            }
+            for _ in 0..self.below(16) {
+                let key = Rcode::from_int(self.below(16) as u8);
+                let value = self.counter();
+                let _ = stats.answer_rcodes.insert(key, value);
+            }
+            for _ in 0..self.below(4) {
+                let key = Rcode::from_int(self.below(16) as u8);
+                let value = self.counter();
+                let _ = stats.query_aggressive.insert(key, value);
+            }
+            for _ in 0..self.below(8) {
+                let key = self.next() as u16;
+                let value = self.counter();
+                let _ = stats.answer_ede.insert(key, value);
+            }
+            for action in &[
+                "nxdomain",
+                "nodata",
+                "passthru",
+                "drop",
+                "tcp-only",
+                "local-data",
+                "disabled",
+                "cname-override",
+            ] {
+                if self.below(2) == 0 {
+                    let key = RpzAction::from_str(action).expect("Known action");
+                    let value = self.counter();
+                    let _ = stats.rpz_actions.insert(key, value);
+                }
+            }
+            for idx in 0..self.below(3) {
+                let value = self.next().to_string();
+                stats.unknown.push((format!("num.future.key{}", idx), value));
+            }
+
+            stats.query_types_other = self.counter();
+            stats.query_classes_other = self.counter();
+            stats.cache.rrset = self.counter();
+            stats.cache.message = self.counter();
+            stats.cache.dnscrypt_shared_secret = self.counter();
+            stats.cache.dnscrypt_nonce = self.counter();
+            stats.modules.iterator = self.counter();
+            stats.modules.validator = self.counter();
+            stats.modules.respip = self.counter();
+            stats.modules.subnet = self.counter();
+            stats.modules.ipsecmod = self.counter();
+            stats.modules.dynlibmod = self.counter();
+            stats.modules.cachedb = self.counter();
+            stats.cache_count.message = self.counter();
+            stats.cache_count.rrset = self.counter();
+            stats.cache_count.infra = self.counter();
+            stats.cache_count.key = self.counter();
+            stats.cache_count.dnscrypt_shared_secret = self.counter();
+            stats.cache_count.dnscrypt_nonce = self.counter();
+            stats.http.query_buffer = self.counter();
+            stats.http.response_buffer = self.counter();
+            stats.flags.qr = self.counter();
+            stats.flags.aa = self.counter();
+            stats.flags.tc = self.counter();
+            stats.flags.rd = self.counter();
+            stats.flags.ra = self.counter();
+            stats.flags.z = self.counter();
+            stats.flags.ad = self.counter();
+            stats.flags.cd = self.counter();
+            stats.mem_streamwait = self.counter();
+            stats.num_query_tcp = self.counter();
+            stats.num_query_tcp_out = self.counter();
+            stats.num_query_tls = self.counter();
+            stats.num_query_tls_resume = self.counter();
+            stats.num_query_ipv6 = self.counter();
+            stats.num_query_edns_present = self.counter();
+            stats.num_query_edns_do = self.counter();
+            stats.num_query_rate_limited = self.counter();
+            stats.num_query_https = self.counter();
+            stats.num_answer_secure = self.counter();
+            stats.num_answer_bogus = self.counter();
+            stats.num_rrset_bogus = self.counter();
+            stats.num_unwanted_queries = self.counter();
+            stats.num_unwanted_replies = self.counter();
+            stats.num_query_dnscrypt_shared_secret_cache_miss = self.counter();
+            stats.num_query_dnscrypt_replay = self.counter();
+            stats.num_query_authzone_up = self.counter();
+            stats.num_query_authzone_down = self.counter();
+            stats.num_query_subnet = self.counter();
+            stats.num_query_subnet_cache = self.counter();
+            stats.num_query_cachedb = self.counter();
+
+            stats
+        }
+    }
+
+    #[test]
+    fn test_round_trip_assets() {
+        for source in &[STATS, STATS_1_13_2] {
+            let stats = assert_ok!(Statistics::from_str(source));
+            let text = stats.to_string();
+            let parsed = assert_ok!(Statistics::from_str(&text));
+
+            assert_eq!(parsed, stats);
+            assert_eq!(parsed.to_string(), text);
+        }
+    }
+
+    #[test]
+    fn test_display_format() {
+        let stats = assert_ok!(Statistics::from_str(STATS));
+        let text = stats.to_string();
+
+        for line in &[
+            "thread1.requestlist.avg=1.03499",
+            "total.recursion.time.avg=0.092751",
+            "total.recursion.time.median=0.0555972",
+            "time.elapsed=32586.319193",
+            "num.query.type.TYPE0=21838",
+            "num.query.type.NSAP-PTR=1",
+            "num.query.class.CLASS5=1",
+            "num.query.opcode.QUERY=1338",
+            "num.answer.rcode.NXDOMAIN=23",
+        ] {
+            assert!(text.lines().any(|l| l == *line), "'{}' is missing", line);
+        }
+    }
+
+    #[test]
+    fn test_write_text_format() {
+        let stats = assert_ok!(Statistics::from_str(STATS_1_13_2));
+        let mut buffer = Vec::new();
+        assert_ok!(stats.write_text(&mut buffer));
+        let text = assert_ok!(String::from_utf8(buffer));
+
+        // Lines of the `unbound` output should be reproduced exactly
+        for line in &[
+            "total.requestlist.avg=0",
+            "total.recursion.time.avg=0.000000",
+            "total.recursion.time.median=0",
+            "time.now=1629245344.643864",
+            "histogram.000000.000000.to.000000.000001=0",
+            "histogram.000000.524288.to.000001.000000=0",
+            "histogram.262144.000000.to.524288.000000=0",
+            "num.query.type.HTTPS=10",
+            "num.query.type.SVCB=45",
+            "num.answer.rcode.NOTIMPL=0",
+            "num.query.aggressive.NXDOMAIN=0",
+        ] {
+            assert!(text.lines().any(|l| l == *line), "'{}' is missing", line);
+        }
+    }
+
+    #[test]
+    fn test_round_trip_random() {
+        for seed in 0..CASES {
+            let stats = Rng::new(seed).statistics();
+            let text = stats.to_string();
+            let parsed = match Statistics::from_str(&text) {
+                Ok(parsed) => parsed,
+                Err(e) => panic!("Seed {}: unable to parse the written statistics: {}\n{}", seed, e, text),
+            };
+
+            assert!(parsed == stats, "Seed {}: round trip mismatch\n{}", seed, text);
+        }
+    }
+}